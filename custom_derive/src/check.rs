@@ -0,0 +1,312 @@
+//! Check the input before any code is generated.
+//!
+//! Problems are returned as a `syn::Error` that points at the attribute or
+//! field that is wrong, the compiler shows them like any other error. The
+//! code generation after this expects valid input and skips what it can not
+//! use, so it does not panic.
+
+use quote::ToTokens;
+
+use crate::case::RenameRule;
+use crate::merge::MergeStrategy;
+use crate::order::{get_ordered_fields, is_ordered};
+use crate::xml::{Collision, Kind};
+use crate::{get_catch_all_field, get_catch_all_fields, get_deserialize_name_value, get_meta_items, has_best_effort_flag, has_own_key, has_serde_flag, is_flag, is_map_type};
+
+/// Where an attribute is written.
+#[derive(Clone, Copy, PartialEq)]
+enum Position {
+    Container,
+    Variant,
+    NamedField,
+    UnnamedField,
+}
+
+/// Serde attributes with a string value. Other serde attributes are left to
+/// serde's own derives.
+const SERDE_STRING_VALUES: &[&str] = &[
+    "rename", "rename_all", "alias", "default", "deserialize_with", "with", "bound", "tag", "content",
+    "skip_serializing_if",
+];
+
+pub fn check_input(ast: &syn::DeriveInput) -> syn::Result<()> {
+    let mut errors = Vec::new();
+    check_attrs(&ast.attrs, Position::Container, &mut errors);
+    match &ast.data {
+        syn::Data::Struct(data_struct) => {
+            check_fields(&data_struct.fields, &mut errors);
+            check_flatten_maps(&data_struct.fields, &mut errors);
+        },
+        syn::Data::Enum(data_enum) => {
+            check_tagging(&ast.attrs, &mut errors);
+            let mut other = None;
+            for variant in &data_enum.variants {
+                check_attrs(&variant.attrs, Position::Variant, &mut errors);
+                check_fields(&variant.fields, &mut errors);
+                check_other_variant(variant, &mut other, &mut errors);
+            }
+        },
+        syn::Data::Union(data_union) => errors.push(syn::Error::new_spanned(
+            data_union.union_token,
+            "`DeserializeBestEffort` can not be derived for a union, use a struct or an enum",
+        )),
+    }
+    let mut errors = errors.into_iter();
+    match errors.next() {
+        Some(mut error) => {
+            errors.for_each(|other| error.combine(other));
+            Err(error)
+        },
+        None => Ok(()),
+    }
+}
+
+fn check_fields(fields: &syn::Fields, errors: &mut Vec<syn::Error>) {
+    match fields {
+        syn::Fields::Named(named) => {
+            for field in &named.named {
+                check_attrs(&field.attrs, Position::NamedField, errors);
+            }
+            let struct_fields: Vec<&syn::Field> = named.named.iter().collect();
+            for field in get_catch_all_fields(&struct_fields).into_iter().skip(1) {
+                errors.push(syn::Error::new_spanned(
+                    field.ident.as_ref().unwrap(),
+                    "only one catch-all field is allowed, the `#[best_effort(catch_all)]` or `#[serde(flatten)]` map",
+                ));
+            }
+            for field in get_ordered_fields(&struct_fields).into_iter().skip(1) {
+                errors.push(syn::Error::new_spanned(
+                    field.ident.as_ref().unwrap(),
+                    "only one `#[best_effort(ordered)]` field is allowed",
+                ));
+            }
+            for field in struct_fields.iter().filter(|field| is_ordered(field)) {
+                let other_attr = field.attrs.iter()
+                    .flat_map(|attr| get_meta_items(attr, "best_effort").unwrap_or_default())
+                    .any(|meta_item| match meta_item {
+                        syn::NestedMeta::Meta(meta) => ["catch_all", "required", "merge"].iter().any(|name| meta.path().is_ident(name)),
+                        syn::NestedMeta::Lit(_) => false,
+                    });
+                if other_attr {
+                    errors.push(syn::Error::new_spanned(
+                        field.ident.as_ref().unwrap(),
+                        "`#[best_effort(ordered)]` can not be used together with `catch_all`, `required` or `merge`",
+                    ));
+                }
+            }
+            for field in &struct_fields {
+                check_kind(field, errors);
+                check_concat(field, errors);
+            }
+        },
+        syn::Fields::Unnamed(unnamed) => {
+            for field in &unnamed.unnamed {
+                check_attrs(&field.attrs, Position::UnnamedField, errors);
+            }
+        },
+        syn::Fields::Unit => (),
+    }
+}
+
+fn check_attrs(attrs: &[syn::Attribute], position: Position, errors: &mut Vec<syn::Error>) {
+    for attr in attrs {
+        match get_meta_items(attr, "serde") {
+            Ok(meta_items) => meta_items.iter().for_each(|meta_item| check_serde_meta_item(meta_item, errors)),
+            Err(err) => errors.push(err),
+        }
+        match get_meta_items(attr, "best_effort") {
+            Ok(meta_items) => meta_items.iter().for_each(|meta_item| check_best_effort_meta_item(meta_item, position, errors)),
+            Err(err) => errors.push(err),
+        }
+    }
+}
+
+fn check_serde_meta_item(meta_item: &syn::NestedMeta, errors: &mut Vec<syn::Error>) {
+    for name in SERDE_STRING_VALUES {
+        if let syn::NestedMeta::Meta(syn::Meta::NameValue(m)) = meta_item {
+            if m.path.is_ident(name) && !matches!(m.lit, syn::Lit::Str(_)) {
+                errors.push(syn::Error::new_spanned(&m.lit, format!("expected a string, like `{} = \"...\"`", name)));
+            }
+        }
+        let lit = match get_deserialize_name_value(meta_item, name) {
+            Some(lit) => lit,
+            None => continue,
+        };
+        let result = match *name {
+            "rename_all" => RenameRule::from_str(&lit.value())
+                .map(|_| ())
+                .map_err(|err| syn::Error::new_spanned(&lit, err)),
+            "default" | "deserialize_with" | "with" | "skip_serializing_if" => parse_path(&lit, name),
+            "bound" => syn::parse_str::<syn::WhereClause>(&format!("where {}", lit.value()))
+                .map(|_| ())
+                .map_err(|err| syn::Error::new_spanned(&lit, format!("failed to parse `bound`: {}", err))),
+            _ => Ok(()),
+        };
+        if let Err(err) = result {
+            errors.push(err);
+        }
+    }
+}
+
+fn check_best_effort_meta_item(meta_item: &syn::NestedMeta, position: Position, errors: &mut Vec<syn::Error>) {
+    let allowed = match position {
+        Position::NamedField => &["catch_all", "required", "merge", "ordered", "attribute", "element", "collision"][..],
+        Position::Variant => &["other"][..],
+        Position::Container | Position::UnnamedField => &[][..],
+    };
+    let name = match meta_item {
+        syn::NestedMeta::Meta(meta) => meta.path().get_ident().map(|ident| ident.to_string()),
+        syn::NestedMeta::Lit(_) => None,
+    };
+    let name = match name {
+        Some(name) if allowed.contains(&name.as_str()) => name,
+        Some(name) if ["catch_all", "required", "merge", "ordered", "attribute", "element", "collision", "other"].contains(&name.as_str()) => {
+            let on = match name.as_str() {
+                "other" => "a variant of an enum",
+                _ => "a named field",
+            };
+            errors.push(syn::Error::new_spanned(meta_item, format!("`{}` can only be used on {}", name, on)));
+            return;
+        },
+        _ => {
+            errors.push(syn::Error::new_spanned(
+                meta_item,
+                format!("unknown best_effort attribute `{}`", meta_item.to_token_stream()),
+            ));
+            return;
+        },
+    };
+    if name == "merge" {
+        match get_deserialize_name_value(meta_item, "merge") {
+            Some(lit) => {
+                let builtin = ["first", "last", "append", "error", "concat"].contains(&lit.value().as_str());
+                if let (false, Err(err)) = (builtin, parse_path(&lit, "merge")) {
+                    errors.push(err);
+                }
+            },
+            None => errors.push(syn::Error::new_spanned(meta_item, "expected `merge = \"...\"`")),
+        }
+    } else if name == "collision" {
+        match get_deserialize_name_value(meta_item, "collision") {
+            Some(lit) if !Collision::NAMES.contains(&lit.value().as_str()) => errors.push(syn::Error::new_spanned(
+                &lit,
+                format!("unknown collision policy `{}`, expected one of {:?}", lit.value(), Collision::NAMES),
+            )),
+            Some(_) => (),
+            None => errors.push(syn::Error::new_spanned(meta_item, "expected `collision = \"...\"`")),
+        }
+    } else if !is_flag(meta_item, &name) {
+        errors.push(syn::Error::new_spanned(meta_item, format!("expected `#[best_effort({})]`", name)));
+    }
+}
+
+// A map with `#[serde(flatten)]` is the catch-all field. Next to a
+// `#[best_effort(catch_all)]` field it would be read as a map field with its
+// own key.
+fn check_flatten_maps(fields: &syn::Fields, errors: &mut Vec<syn::Error>) {
+    let struct_fields: Vec<&syn::Field> = match fields {
+        syn::Fields::Named(named) => named.named.iter().collect(),
+        _ => return,
+    };
+    let catch_all = get_catch_all_field(&struct_fields);
+    for field in &struct_fields {
+        if has_serde_flag(&field.attrs, "flatten") && is_map_type(&field.ty) && has_own_key(field, catch_all) {
+            errors.push(syn::Error::new_spanned(
+                field.ident.as_ref().unwrap(),
+                "only one catch-all field is allowed, the `#[best_effort(catch_all)]` or `#[serde(flatten)]` map",
+            ));
+        }
+    }
+}
+
+// `attribute` or `element` (not both) on a field with its own key, and
+// `collision` only together with one of them.
+fn check_kind(field: &syn::Field, errors: &mut Vec<syn::Error>) {
+    let attrs = &field.attrs;
+    let ident = field.ident.as_ref().unwrap();
+    let has_collision = attrs.iter()
+        .flat_map(|attr| get_meta_items(attr, "best_effort").unwrap_or_default())
+        .any(|meta_item| get_deserialize_name_value(&meta_item, "collision").is_some());
+    match Kind::from_attrs(attrs) {
+        Some(_) if has_best_effort_flag(attrs, "attribute") && has_best_effort_flag(attrs, "element") => errors.push(
+            syn::Error::new_spanned(ident, "a field can not be both `#[best_effort(attribute)]` and `#[best_effort(element)]`"),
+        ),
+        Some(_) if has_best_effort_flag(attrs, "catch_all") || is_ordered(field) || has_serde_flag(attrs, "flatten")
+            || has_serde_flag(attrs, "skip") || has_serde_flag(attrs, "skip_deserializing") => errors.push(
+            syn::Error::new_spanned(ident, "`attribute` and `element` can only be used on a field with its own key"),
+        ),
+        Some(_) => (),
+        None if has_collision => errors.push(syn::Error::new_spanned(
+            ident,
+            "`collision` can only be used together with `#[best_effort(attribute)]` or `#[best_effort(element)]`",
+        )),
+        None => (),
+    }
+}
+
+// `#[best_effort(merge = "concat")]` appends text, so the field is a `String`.
+fn check_concat(field: &syn::Field, errors: &mut Vec<syn::Error>) {
+    if matches!(MergeStrategy::from_attrs(&field.attrs), MergeStrategy::Concat) && !is_string_type(&field.ty) {
+        errors.push(syn::Error::new_spanned(&field.ty, "`#[best_effort(merge = \"concat\")]` can only be used on a `String`"));
+    }
+}
+
+// `String` or `std::string::String`
+fn is_string_type(type_: &syn::Type) -> bool {
+    let path = match type_ {
+        syn::Type::Path(syn::TypePath{ qself: None, path }) => path,
+        _ => return false,
+    };
+    if path.segments.iter().any(|segment| !segment.arguments.is_empty()) {
+        return false;
+    }
+    let segments: Vec<String> = path.segments.iter().map(|segment| segment.ident.to_string()).collect();
+    segments == ["String"] || segments == ["std", "string", "String"] || segments == ["alloc", "string", "String"]
+}
+
+fn parse_path(lit: &syn::LitStr, name: &str) -> syn::Result<()> {
+    lit.parse::<syn::Path>()
+        .map(|_| ())
+        .map_err(|err| syn::Error::new_spanned(lit, format!("failed to parse `{} = {:?}` as a path: {}", name, lit.value(), err)))
+}
+
+// `#[serde(content = "...")]` needs `#[serde(tag = "...")]`
+fn check_tagging(attrs: &[syn::Attribute], errors: &mut Vec<syn::Error>) {
+    let meta_items: Vec<syn::NestedMeta> = attrs.iter()
+        .flat_map(|attr| get_meta_items(attr, "serde").unwrap_or_default())
+        .collect();
+    let has_tag = meta_items.iter().any(|meta_item| get_deserialize_name_value(meta_item, "tag").is_some());
+    for meta_item in &meta_items {
+        if let (false, Some(content)) = (has_tag, get_deserialize_name_value(meta_item, "content")) {
+            errors.push(syn::Error::new_spanned(
+                content,
+                "`#[serde(content = \"...\")]` can only be used together with `#[serde(tag = \"...\")]`",
+            ));
+        }
+    }
+}
+
+// Only one `#[best_effort(other)]` variant, and it is a unit or a newtype variant.
+fn check_other_variant<'a>(variant: &'a syn::Variant, other: &mut Option<&'a syn::Variant>, errors: &mut Vec<syn::Error>) {
+    let marked = variant.attrs.iter()
+        .flat_map(|attr| get_meta_items(attr, "best_effort").unwrap_or_default())
+        .any(|meta_item| is_flag(&meta_item, "other"));
+    if !marked {
+        return;
+    }
+    match &variant.fields {
+        syn::Fields::Unit => (),
+        syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => (),
+        _ => errors.push(syn::Error::new_spanned(
+            &variant.ident,
+            "`#[best_effort(other)]` must be on a unit variant or a newtype variant",
+        )),
+    }
+    match other {
+        Some(_) => errors.push(syn::Error::new_spanned(
+            &variant.ident,
+            "only one `#[best_effort(other)]` variant is allowed",
+        )),
+        None => *other = Some(variant),
+    }
+}
@@ -0,0 +1,568 @@
+//! Generate the `Deserialize` implementation for enums.
+//!
+//! The same representations as in serde are supported:
+//! https://serde.rs/enum-representations.html
+//! Externally tagged enums are read with `deserialize_enum`.
+//! Internally tagged, adjacently tagged and untagged enums first read the
+//! input into a `serde_json::Value` and then pick the variant from that value.
+//! When no variant matches, the variant marked with `#[best_effort(other)]`
+//! is used. In strict mode there is no fallback, that is an error.
+
+use quote::{quote, format_ident};
+use syn::ext::IdentExt;
+
+use crate::case::RenameRule;
+use crate::generics::TypeName;
+use crate::{
+    get_alias_attrs, get_deserialize_name_value, get_enum_ident,
+    get_field_name, get_named_fields, get_rename_all_attr, get_rename_attr,
+    get_serde_meta_items, has_best_effort_flag, has_serde_flag, impl_named_fields_visitor,
+    impl_tuple_visitor,
+};
+
+/// How the variant is found in the input.
+/// Set with `#[serde(tag = "...")]`, `#[serde(tag = "...", content = "...")]`
+/// or `#[serde(untagged)]` on the enum.
+enum Tagging {
+    External,
+    Internal{ tag: String },
+    Adjacent{ tag: String, content: String },
+    Untagged,
+}
+
+pub fn impl_deserialize_best_effort_enum(ast: &syn::DeriveInput, data_enum: &syn::DataEnum) -> proc_macro2::TokenStream {
+    let name = TypeName::new(ast);
+    let variants: Vec<&syn::Variant> = data_enum.variants.iter().collect();
+    let rename_all = get_rename_all_attr(&ast.attrs);
+    let other = get_other_variant(&variants);
+
+    let body = match get_tagging(&ast.attrs) {
+        Tagging::External => {
+            let variant_enum_and_visitor = impl_variant_enum_visitor(&variants, rename_all);
+            let variants_array = get_variants_array(&variants, rename_all);
+            let enum_visitor = impl_external_enum_visitor(&variants, &name, other);
+            let visitor_name = format_ident!("Enum{}Visitor", name.ident);
+            let visitor = name.visitor_new(&visitor_name);
+            quote!{
+                #variant_enum_and_visitor
+
+                #variants_array
+
+                #enum_visitor
+                deserializer.deserialize_enum(stringify!(#name), VARIANTS, #visitor)
+            }
+        },
+        Tagging::Internal{ tag } => {
+            let variant_enum_and_visitor = impl_variant_enum_visitor(&variants, rename_all);
+            let variants_array = get_variants_array(&variants, rename_all);
+            let variant_match = get_buffered_variant_match(&variants, &name, other, &tag);
+            quote!{
+                #variant_enum_and_visitor
+
+                #variants_array
+
+                let content = deserialize_best_effort::unwrap_text_nodes(
+                    <serde_json::Value as serde::de::Deserialize>::deserialize(deserializer)?
+                );
+                // The tag is next to the fields of the variant.
+                let (tag, content) = match content {
+                    serde_json::Value::Object(mut map) => (map.remove(#tag), serde_json::Value::Object(map)),
+                    content => (None, content),
+                };
+                #variant_match
+            }
+        },
+        Tagging::Adjacent{ tag, content } => {
+            let variant_enum_and_visitor = impl_variant_enum_visitor(&variants, rename_all);
+            let variants_array = get_variants_array(&variants, rename_all);
+            let variant_match = get_buffered_variant_match(&variants, &name, other, &tag);
+            quote!{
+                #variant_enum_and_visitor
+
+                #variants_array
+
+                let content = deserialize_best_effort::unwrap_text_nodes(
+                    <serde_json::Value as serde::de::Deserialize>::deserialize(deserializer)?
+                );
+                // The tag and the variant are both fields of the object.
+                let (tag, content) = match content {
+                    serde_json::Value::Object(mut map) => (
+                        map.remove(#tag),
+                        map.remove(#content).unwrap_or(serde_json::Value::Null),
+                    ),
+                    _ => (None, serde_json::Value::Null),
+                };
+                #variant_match
+            }
+        },
+        Tagging::Untagged => {
+            let untagged_match = get_untagged_match(&variants, &name, other);
+            quote!{
+                let content = deserialize_best_effort::unwrap_text_nodes(
+                    <serde_json::Value as serde::de::Deserialize>::deserialize(deserializer)?
+                );
+                #untagged_match
+            }
+        },
+    };
+
+    let impl_marker = name.impl_de_trait(quote!{deserialize_best_effort::DeserializeBestEffort<'de>});
+    let impl_deserialize = name.impl_de_trait(quote!{serde::de::Deserialize<'de>});
+    quote! {
+        #impl_marker {}
+        #impl_deserialize {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::de::Deserializer<'de>,
+            {
+                // A closure, so `strict_result` also gets the values of `return`.
+                let __value: Result<Self, D::Error> = (move || { #body })();
+                deserialize_best_effort::strict_result(__value)
+            }
+        }
+    }
+}
+
+fn get_tagging(attrs: &[syn::Attribute]) -> Tagging {
+    let mut tag = None;
+    let mut content = None;
+    for attr in attrs{
+        for meta_item in get_serde_meta_items(attr){
+            if let Some(lit) = get_deserialize_name_value(&meta_item, "tag") {
+                tag = Some(lit.value());
+            }
+            if let Some(lit) = get_deserialize_name_value(&meta_item, "content") {
+                content = Some(lit.value());
+            }
+        }
+    }
+    if has_serde_flag(attrs, "untagged") {
+        return Tagging::Untagged;
+    }
+    match (tag, content) {
+        (Some(tag), Some(content)) => Tagging::Adjacent{ tag, content },
+        (Some(tag), None) => Tagging::Internal{ tag },
+        // `content` without `tag` is reported by `check.rs`.
+        (None, _) => Tagging::External,
+    }
+}
+
+/// Get the variant that is used when no other variant matches.
+/// This is the variant marked with `#[best_effort(other)]`, it can be a unit
+/// variant or a newtype variant.
+/// Example
+/// ```ignore
+/// enum Payment{
+///     Card(Card),
+///     #[best_effort(other)]
+///     Other(Value),
+/// }
+/// ```
+/// Will return `Some(Other)`.
+/// `check.rs` makes sure there is only one, and that it has the right shape.
+fn get_other_variant<'a>(variants: &[&'a syn::Variant]) -> Option<&'a syn::Variant> {
+    variants.iter()
+        .find(|variant| has_best_effort_flag(&variant.attrs, "other"))
+        .copied()
+}
+
+/// Get the name of the variant as it appears in the input.
+/// This is the variant name with `#[serde(rename = "name")]` or the container
+/// `#[serde(rename_all = "...")]` rule applied.
+fn get_variant_name(variant: &syn::Variant, rename_all: RenameRule) -> String {
+    if let Some(rename) = get_rename_attr(&variant.attrs) {
+        return rename;
+    }
+    rename_all.apply_to_variant(&variant.ident.unraw().to_string())
+}
+
+fn get_variants_array(variants: &[&syn::Variant], rename_all: RenameRule) -> proc_macro2::TokenStream{
+    let mut parse_gen = quote!{};
+    for variant in variants{
+        // Ex: `Card(Card),` will add `"Card",` to the list
+        let variant_name = get_variant_name(variant, rename_all);
+        parse_gen = quote!{
+            #parse_gen #variant_name,
+        };
+        for alias in get_alias_attrs(&variant.attrs){
+            parse_gen = quote!{
+                #parse_gen #alias,
+            };
+        }
+    }
+    quote!{
+        const VARIANTS: &[&str] = &[#parse_gen];
+    }
+}
+
+fn impl_variant_enum_visitor(variants: &[&syn::Variant], rename_all: RenameRule) -> proc_macro2::TokenStream{
+    let mut variant_enum = quote!{};
+    let mut variant_match = quote!{};
+    for variant in variants{
+        let variant_ident = get_enum_ident(&variant.ident);
+        let variant_name = get_variant_name(variant, rename_all);
+        variant_enum = quote!{
+            #variant_enum
+            #variant_ident,
+        };
+        variant_match = quote!{
+            #variant_match
+            // "card" => Ok(Variant::Card),
+            #variant_name => Ok(Variant::#variant_ident),
+        };
+        for alias in get_alias_attrs(&variant.attrs){
+            variant_match = quote!{
+                #variant_match
+                #alias => Ok(Variant::#variant_ident),
+            };
+        }
+    }
+
+    quote!{
+        // enum Variant { Card, Invoice, __Unknown(String) };
+        // (`__Unknown`, so it can not be the same as a variant of the enum)
+        enum Variant {
+            #variant_enum
+            __Unknown(String),
+        }
+
+        impl<'de> serde::de::Deserialize<'de> for Variant {
+            fn deserialize<D>(deserializer: D) -> Result<Variant, D::Error>
+            where
+                D: serde::de::Deserializer<'de>,
+            {
+                struct VariantVisitor;
+
+                impl<'de> serde::de::Visitor<'de> for VariantVisitor {
+                    type Value = Variant;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        formatter.write_str("variant identifier")
+                    }
+
+                    fn visit_str<E>(self, value: &str) -> Result<Variant, E>
+                    where
+                        E: serde::de::Error,
+                    {
+                        match value {
+                            #variant_match
+                            _ => Ok(Variant::__Unknown(value.to_string())),
+                        }
+                    }
+                }
+                deserializer.deserialize_identifier(VariantVisitor)
+            }
+        }
+    }
+}
+
+fn impl_external_enum_visitor(variants: &[&syn::Variant], name: &TypeName, other: Option<&syn::Variant>) -> proc_macro2::TokenStream{
+    let mut variant_match = quote!{};
+    for variant in variants{
+        let variant_ident = get_enum_ident(&variant.ident);
+        let ident = &variant.ident;
+        let create_variant = match &variant.fields {
+            syn::Fields::Unit => quote!{
+                let _ = serde::de::VariantAccess::unit_variant(variant);
+                Ok(#name::#ident)
+            },
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => quote!{
+                Ok(#name::#ident(deserialize_best_effort::unwrap_or_report(
+                    serde::de::VariantAccess::newtype_variant(variant), stringify!(#ident)
+                )))
+            },
+            syn::Fields::Unnamed(fields) => {
+                let types: Vec<&syn::Type> = fields.unnamed.iter().map(|field| &field.ty).collect();
+                let len = types.len();
+                let visitor_name = format_ident!("Variant{}Visitor", ident);
+                let visitor = name.visitor_new(&visitor_name);
+                let tuple_visitor = impl_tuple_visitor(&types, name, &quote!{#name::#ident}, &visitor_name);
+                quote!{
+                    #tuple_visitor
+                    serde::de::VariantAccess::tuple_variant(variant, #len, #visitor)
+                }
+            },
+            syn::Fields::Named(_) => {
+                let visitor_name = format_ident!("Variant{}Visitor", ident);
+                let visitor = name.visitor_new(&visitor_name);
+                let fields_visitor = impl_struct_variant_visitor(variant, name, &visitor_name);
+                quote!{
+                    #fields_visitor
+                    serde::de::VariantAccess::struct_variant(variant, FIELDS, #visitor)
+                }
+            },
+        };
+        variant_match = quote!{
+            #variant_match
+            Variant::#variant_ident => {
+                #create_variant
+            }
+        };
+    }
+    let unknown_match = match other {
+        Some(other) => {
+            let ident = &other.ident;
+            // In strict mode an unknown variant is an error, also when there
+            // is an `other` variant.
+            let strict_match = quote!{
+                Variant::__Unknown(variant_name) if deserialize_best_effort::is_strict() => {
+                    Err(serde::de::Error::unknown_variant(&variant_name, VARIANTS))
+                }
+            };
+            match &other.fields {
+                syn::Fields::Unit => quote!{
+                    #strict_match
+                    Variant::__Unknown(_variant_name) => {
+                        let _ = serde::de::VariantAccess::newtype_variant::<serde::de::IgnoredAny>(variant);
+                        Ok(#name::#ident)
+                    }
+                },
+                _ => quote!{
+                    #strict_match
+                    Variant::__Unknown(_variant_name) => {
+                        Ok(#name::#ident(deserialize_best_effort::unwrap_or_report(
+                            serde::de::VariantAccess::newtype_variant(variant), stringify!(#ident)
+                        )))
+                    }
+                },
+            }
+        },
+        None => quote!{
+            Variant::__Unknown(variant_name) => {
+                Err(serde::de::Error::unknown_variant(&variant_name, VARIANTS))
+            }
+        },
+    };
+    let visitor_name = format_ident!("Enum{}Visitor", name.ident);
+    let visitor_struct = name.visitor_struct(&visitor_name);
+    let visitor_impl = name.visitor_impl(&visitor_name);
+    let name_ty = name.ty();
+
+    quote!{
+        #visitor_struct
+
+        #visitor_impl {
+            type Value = #name_ty;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str(&format!("enum {}",stringify!(#name)))
+            }
+
+            fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::EnumAccess<'de>,
+            {
+                let (variant_key, variant) = serde::de::EnumAccess::variant::<Variant>(data)?;
+                match variant_key {
+                    #variant_match
+                    #unknown_match
+                }
+            }
+        }
+    }
+}
+
+fn impl_struct_variant_visitor(variant: &syn::Variant, name: &TypeName, visitor_name: &syn::Ident) -> proc_macro2::TokenStream{
+    let ident = &variant.ident;
+    let fields = get_named_fields(&variant.fields);
+    // `#[serde(rename_all = "...")]` on a variant renames the fields of that variant.
+    let rename_all = get_rename_all_attr(&variant.attrs);
+    impl_named_fields_visitor(&fields, name, &quote!{#name::#ident}, visitor_name, rename_all, None)
+}
+
+/// Create the variant from the buffered `content` value.
+/// This is used by all representations except externally tagged enums.
+fn get_variant_from_content(variant: &syn::Variant, name: &TypeName) -> proc_macro2::TokenStream{
+    let ident = &variant.ident;
+    match &variant.fields {
+        syn::Fields::Unit => quote!{
+            Ok(#name::#ident)
+        },
+        syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => quote!{
+            Ok(#name::#ident(deserialize_best_effort::deserialize_or_default(content, stringify!(#ident))))
+        },
+        syn::Fields::Unnamed(fields) => {
+            let types: Vec<&syn::Type> = fields.unnamed.iter().map(|field| &field.ty).collect();
+            let visitor_name = format_ident!("Variant{}Visitor", ident);
+            let visitor = name.visitor_new(&visitor_name);
+            let tuple_visitor = impl_tuple_visitor(&types, name, &quote!{#name::#ident}, &visitor_name);
+            quote!{
+                #tuple_visitor
+                let content = match content {
+                    serde_json::Value::Array(_) => content,
+                    _ => serde_json::Value::Array(Vec::new()),
+                };
+                serde::de::Deserializer::deserialize_seq(content, #visitor)
+                    .map_err(serde::de::Error::custom)
+            }
+        },
+        syn::Fields::Named(_) => {
+            let visitor_name = format_ident!("Variant{}Visitor", ident);
+            let visitor = name.visitor_new(&visitor_name);
+            let fields_visitor = impl_struct_variant_visitor(variant, name, &visitor_name);
+            quote!{
+                #fields_visitor
+                let content = match content {
+                    serde_json::Value::Object(_) => content,
+                    _ => serde_json::Value::Object(Default::default()),
+                };
+                serde::de::Deserializer::deserialize_map(content, #visitor)
+                    .map_err(serde::de::Error::custom)
+            }
+        },
+    }
+}
+
+fn get_other_from_content(other: &syn::Variant, name: &TypeName) -> proc_macro2::TokenStream{
+    let ident = &other.ident;
+    match &other.fields {
+        syn::Fields::Unit => quote!{
+            Ok(#name::#ident)
+        },
+        _ => quote!{
+            Ok(#name::#ident(deserialize_best_effort::deserialize_or_default(content, stringify!(#ident))))
+        },
+    }
+}
+
+/// Match the `tag` value to a variant and create it from `content`.
+/// Used for internally and adjacently tagged enums.
+fn get_buffered_variant_match(
+    variants: &[&syn::Variant],
+    name: &TypeName,
+    other: Option<&syn::Variant>,
+    tag: &str,
+) -> proc_macro2::TokenStream{
+    let mut variant_match = quote!{};
+    for variant in variants{
+        let variant_ident = get_enum_ident(&variant.ident);
+        let create_variant = get_variant_from_content(variant, name);
+        variant_match = quote!{
+            #variant_match
+            Variant::#variant_ident => {
+                #create_variant
+            }
+        };
+    }
+    let (missing_tag, unknown_match) = match other {
+        Some(other) => {
+            let create_other = get_other_from_content(other, name);
+            (
+                quote!{
+                    if deserialize_best_effort::is_strict() {
+                        return Err(serde::de::Error::missing_field(#tag));
+                    }
+                    Variant::__Unknown(String::new())
+                },
+                quote!{
+                    Variant::__Unknown(variant_name) if deserialize_best_effort::is_strict() => {
+                        Err(serde::de::Error::unknown_variant(&variant_name, VARIANTS))
+                    }
+                    Variant::__Unknown(_variant_name) => {
+                        #create_other
+                    }
+                },
+            )
+        },
+        None => (
+            quote!{ return Err(serde::de::Error::missing_field(#tag)) },
+            quote!{
+                Variant::__Unknown(variant_name) => {
+                    Err(serde::de::Error::unknown_variant(&variant_name, VARIANTS))
+                }
+            },
+        ),
+    };
+    quote!{
+        let variant_key = match tag {
+            Some(tag) => <Variant as serde::de::Deserialize>::deserialize(tag.clone())
+                .unwrap_or_else(|_| Variant::__Unknown(tag.to_string())),
+            None => { #missing_tag },
+        };
+        match variant_key {
+            #variant_match
+            #unknown_match
+        }
+    }
+}
+
+/// Try the variants in order and use the first one that fits `content`.
+/// - A struct variant fits when `content` is an object with at least one of its fields.
+/// - A newtype variant fits when its type can be deserialized from `content`.
+/// - A tuple variant fits when `content` is an array.
+/// - A unit variant fits when `content` is empty.
+fn get_untagged_match(variants: &[&syn::Variant], name: &TypeName, other: Option<&syn::Variant>) -> proc_macro2::TokenStream{
+    let mut parse_gen = quote!{};
+    for variant in variants{
+        if other.map(|other| other.ident == variant.ident) == Some(true) {
+            continue;
+        }
+        let ident = &variant.ident;
+        let try_variant = match &variant.fields {
+            syn::Fields::Unit => quote!{
+                if content.is_null() {
+                    return Ok(#name::#ident);
+                }
+            },
+            // Without the strict attempt the first newtype variant would
+            // always match, with the defaults of all fields it does not know.
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => quote!{
+                let value = deserialize_best_effort::try_strict(|| {
+                    serde::de::Deserialize::deserialize(content.clone())
+                });
+                if let Ok(value) = value {
+                    return Ok(#name::#ident(value));
+                }
+            },
+            syn::Fields::Unnamed(_) => {
+                let create_variant = get_variant_from_content(variant, name);
+                quote!{
+                    if content.is_array() {
+                        return { #create_variant };
+                    }
+                }
+            },
+            syn::Fields::Named(_) => {
+                let rename_all = get_rename_all_attr(&variant.attrs);
+                let mut field_names = Vec::new();
+                for field in get_named_fields(&variant.fields){
+                    field_names.push(get_field_name(field, rename_all));
+                    field_names.extend(get_alias_attrs(&field.attrs));
+                }
+                let create_variant = get_variant_from_content(variant, name);
+                quote!{
+                    if let serde_json::Value::Object(map) = &content {
+                        if [#(#field_names),*].iter().any(|key| map.contains_key(*key)) {
+                            return { #create_variant };
+                        }
+                    }
+                }
+            },
+        };
+        parse_gen = quote!{
+            #parse_gen
+            #try_variant
+        };
+    }
+    let error = quote!{
+        Err(serde::de::Error::custom(format!(
+            "data did not match any variant of untagged enum {}", stringify!(#name)
+        )))
+    };
+    let no_match = match other {
+        Some(other) => {
+            let create_other = get_other_from_content(other, name);
+            quote!{
+                if deserialize_best_effort::is_strict() {
+                    return #error;
+                }
+                #create_other
+            }
+        },
+        None => error,
+    };
+    quote!{
+        #parse_gen
+        #no_match
+    }
+}
@@ -0,0 +1,78 @@
+//! `#[serde(flatten)]` of a field that is a struct.
+//!
+//! The keys of a flattened struct are in the same map as the keys of the
+//! struct it is in. Every named struct implements
+//! `DeserializeBestEffortFlatten`: a key that is not a field of the outer
+//! struct is given to `flatten_next_value` of each flattened field. That
+//! field reads the value if the key is one of its own fields (with its own
+//! merge rules, and its own flattened fields) and returns `false` otherwise.
+//! Only keys that no flattened struct uses end up in the catch-all field.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::{
+    FieldsIn, RenameRule, TypeName, get_catch_all_field, get_field_enum, get_fields_data, get_flatten_fields,
+    set_struct_create_object, set_struct_visit_map_enum_match, set_struct_visit_map_required_check,
+    set_struct_visit_map_variable,
+};
+
+/// Implement `DeserializeBestEffortFlatten` for a struct with named fields.
+/// `Seen` holds the `seen_fieldN` flags of the struct, followed by the `Seen`
+/// of every flattened field.
+pub fn impl_flatten(
+    struct_fields: &[&syn::Field],
+    name: &TypeName,
+    rename_all: RenameRule,
+    container_default: Option<&TokenStream>,
+) -> TokenStream {
+    let catch_all = get_catch_all_field(struct_fields);
+    let field_enum = get_field_enum(struct_fields, rename_all, catch_all);
+    let variable_init = set_struct_visit_map_variable(struct_fields, catch_all, container_default, FieldsIn::Flatten);
+    let enum_match = set_struct_visit_map_enum_match(struct_fields, rename_all, catch_all, container_default, FieldsIn::Flatten);
+    let required_check = set_struct_visit_map_required_check(struct_fields, rename_all, catch_all, FieldsIn::Flatten);
+    let create_object = set_struct_create_object(&get_fields_data(struct_fields), &quote!{#name});
+
+    let len = struct_fields.len();
+    let flatten_types: Vec<&syn::Type> = get_flatten_fields(struct_fields, catch_all).into_iter()
+        .map(|(_, field)| &field.ty)
+        .collect();
+    let flatten_trait = quote!{ deserialize_best_effort::DeserializeBestEffortFlatten<'de> };
+    let impl_flatten = name.impl_de_trait(flatten_trait.clone());
+    quote!{
+        #impl_flatten {
+            type Seen = ([bool; #len], #(<#flatten_types as #flatten_trait>::Seen,)*);
+
+            #[allow(unused_mut)]
+            fn flatten_default() -> Self {
+                #variable_init
+                #create_object
+            }
+
+            fn flatten_seen() -> Self::Seen {
+                ([false; #len], #(<#flatten_types as #flatten_trait>::flatten_seen(),)*)
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            fn flatten_next_value<A>(&mut self, seen: &mut Self::Seen, __key: &str, __position: usize, mut __map: &mut A) -> Result<bool, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                #field_enum
+
+                let __key = Field::from_key(__key);
+                #enum_match
+                Ok(true)
+            }
+
+            #[allow(unused_variables)]
+            fn flatten_end<E>(&self, seen: &Self::Seen) -> Result<(), E>
+            where
+                E: serde::de::Error,
+            {
+                #required_check
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Generics and lifetimes of the type that derives the trait.
+//!
+//! The visitors are declared inside the `deserialize` function, so they can
+//! not use the generics of the `impl` block. Every visitor gets its own copy
+//! of the generics and a `PhantomData` marker to use them.
+
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+
+use crate::{get_default_fn_attr, get_deserialize_name_value, get_serde_meta_items};
+
+/// The name of the type that derives the trait, together with its generics.
+/// In `quote!` it is written as just the name, so it can be used for
+/// `stringify!(#name)`, `#name{..}` and `#name::Variant`.
+pub struct TypeName<'a> {
+    pub ident: &'a syn::Ident,
+    /// The generics as written on the type.
+    generics: &'a syn::Generics,
+    /// The generics with `'de` added and the bounds needed to deserialize.
+    de_generics: syn::Generics,
+    /// The generics with the bounds needed to serialize.
+    ser_generics: syn::Generics,
+}
+
+impl<'a> TypeName<'a> {
+    pub fn new(ast: &'a syn::DeriveInput) -> Self {
+        TypeName{
+            ident: &ast.ident,
+            generics: &ast.generics,
+            de_generics: get_de_generics(ast),
+            ser_generics: get_ser_generics(ast),
+        }
+    }
+
+    /// The type with its generics. Ex: `Envelope<T>`
+    pub fn ty(&self) -> TokenStream {
+        let ident = self.ident;
+        let (_, ty_generics, _) = self.generics.split_for_impl();
+        quote!{ #ident #ty_generics }
+    }
+
+    /// The start of an `impl` block of a trait with a `'de` lifetime.
+    /// Ex: `impl<'de, T> DeserializeBestEffort<'de> for Envelope<T> where T: ...`
+    pub fn impl_de_trait(&self, trait_: TokenStream) -> TokenStream {
+        let ty = self.ty();
+        let (impl_generics, _, where_clause) = self.de_generics.split_for_impl();
+        quote!{ impl #impl_generics #trait_ for #ty #where_clause }
+    }
+
+    /// The start of an `impl` block of a trait for serializing.
+    /// Ex: `impl<T> Serialize for Envelope<T> where T: ...`
+    pub fn impl_ser_trait(&self, trait_: TokenStream) -> TokenStream {
+        let ty = self.ty();
+        let (impl_generics, _, where_clause) = self.ser_generics.split_for_impl();
+        quote!{ impl #impl_generics #trait_ for #ty #where_clause }
+    }
+
+    /// Declare a visitor for this type.
+    /// Ex: `struct StructEnvelopeVisitor<T> { marker: PhantomData<Envelope<T>> }`
+    pub fn visitor_struct(&self, visitor_name: &syn::Ident) -> TokenStream {
+        let ty = self.ty();
+        let (impl_generics, _, where_clause) = self.generics.split_for_impl();
+        quote!{
+            struct #visitor_name #impl_generics #where_clause {
+                marker: std::marker::PhantomData<#ty>,
+            }
+        }
+    }
+
+    /// The start of the `impl Visitor` block of a visitor for this type.
+    pub fn visitor_impl(&self, visitor_name: &syn::Ident) -> TokenStream {
+        let (impl_generics, _, where_clause) = self.de_generics.split_for_impl();
+        let (_, ty_generics, _) = self.generics.split_for_impl();
+        quote!{ impl #impl_generics serde::de::Visitor<'de> for #visitor_name #ty_generics #where_clause }
+    }
+
+    /// Create a visitor for this type.
+    pub fn visitor_new(&self, visitor_name: &syn::Ident) -> TokenStream {
+        quote!{ #visitor_name{ marker: std::marker::PhantomData } }
+    }
+}
+
+impl<'a> ToTokens for TypeName<'a> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.ident.to_tokens(tokens);
+    }
+}
+
+/// Add the `'de` lifetime to the generics of the type.
+/// `'de` has to outlive all lifetimes of the type, so `&'a str` can be
+/// borrowed from the input.
+/// Every type parameter gets the bounds needed to use it as a field:
+/// `T: Deserialize<'de> + DeserializeBestEffortTypes<'de, T> + Default`.
+/// `Default` is left out when the parameter is only used in fields with
+/// `#[serde(default = "...")]`.
+/// Like in serde, `#[serde(bound = "...")]` replaces these bounds.
+fn get_de_generics(ast: &syn::DeriveInput) -> syn::Generics {
+    let mut generics = ast.generics.clone();
+    let lifetimes: Vec<syn::Lifetime> = generics.lifetimes()
+        .map(|lifetime_def| lifetime_def.lifetime.clone())
+        .collect();
+    let type_params: Vec<syn::Ident> = generics.type_params()
+        .map(|type_param| type_param.ident.clone())
+        .collect();
+
+    let where_clause = generics.make_where_clause();
+    match get_bound_attr(&ast.attrs) {
+        Some(predicates) => where_clause.predicates.extend(predicates),
+        None => {
+            for param in type_params {
+                let default_bound = if needs_default(ast, &param) {
+                    quote!{ + Default }
+                } else {
+                    quote!{}
+                };
+                where_clause.predicates.push(syn::parse_quote!{
+                    #param: serde::de::Deserialize<'de> + deserialize_best_effort::DeserializeBestEffortTypes<'de, #param> #default_bound
+                });
+            }
+        },
+    }
+
+    let de_lifetime: syn::LifetimeDef = if lifetimes.is_empty() {
+        syn::parse_quote!{ 'de }
+    } else {
+        syn::parse_quote!{ 'de: #(#lifetimes)+* }
+    };
+    generics.params.insert(0, syn::GenericParam::Lifetime(de_lifetime));
+    generics
+}
+
+/// Every type parameter gets the bounds needed to write it as a field:
+/// `T: Serialize + SerializeBestEffortTypes`, or the `#[serde(bound = "...")]`.
+fn get_ser_generics(ast: &syn::DeriveInput) -> syn::Generics {
+    let mut generics = ast.generics.clone();
+    let type_params: Vec<syn::Ident> = generics.type_params()
+        .map(|type_param| type_param.ident.clone())
+        .collect();
+
+    let where_clause = generics.make_where_clause();
+    match get_bound_attr(&ast.attrs) {
+        Some(predicates) => where_clause.predicates.extend(predicates),
+        None => {
+            for param in type_params {
+                where_clause.predicates.push(syn::parse_quote!{
+                    #param: serde::ser::Serialize + deserialize_best_effort::SerializeBestEffortTypes
+                });
+            }
+        },
+    }
+    generics
+}
+
+/// Is `param` used in a field that starts with `Default::default()`.
+fn needs_default(ast: &syn::DeriveInput, param: &syn::Ident) -> bool {
+    let fields: Vec<&syn::Field> = match &ast.data {
+        syn::Data::Struct(data_struct) => data_struct.fields.iter().collect(),
+        syn::Data::Enum(data_enum) => data_enum.variants.iter()
+            .flat_map(|variant| variant.fields.iter())
+            .collect(),
+        syn::Data::Union(_) => Vec::new(),
+    };
+    fields.iter()
+        .filter(|field| get_default_fn_attr(&field.attrs).is_none())
+        .any(|field| contains_ident(field.ty.to_token_stream(), param))
+}
+
+fn contains_ident(tokens: TokenStream, ident: &syn::Ident) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(token) => token == *ident,
+        proc_macro2::TokenTree::Group(group) => contains_ident(group.stream(), ident),
+        _ => false,
+    })
+}
+
+// Parse `#[serde(bound = "T: MyTrait")]` and `#[serde(bound(deserialize = "T: MyTrait"))]`
+fn get_bound_attr(attrs: &[syn::Attribute]) -> Option<Vec<syn::WherePredicate>> {
+    let mut bound = None;
+    for attr in attrs{
+        for meta_item in get_serde_meta_items(attr){
+            if let Some(lit) = get_deserialize_name_value(&meta_item, "bound") {
+                // A bound that can not be parsed is reported by `check.rs`.
+                if let Ok(where_clause) = syn::parse_str::<syn::WhereClause>(&format!("where {}", lit.value())) {
+                    bound = Some(where_clause.predicates.into_iter().collect());
+                }
+            }
+        }
+    }
+    bound
+}
@@ -1,55 +1,419 @@
 
 use proc_macro::TokenStream;
-use proc_macro2;
-use quote::{quote,format_ident};
-use syn;
+use quote::{quote,quote_spanned,format_ident};
+use syn::ext::IdentExt;
+use syn::spanned::Spanned;
 
+mod case;
+mod check;
+mod enums;
+mod flatten;
+mod generics;
+mod merge;
+mod order;
+mod serialize;
+mod xml;
+use case::RenameRule;
+use generics::TypeName;
+use merge::{MergeStrategy, get_seen_ident};
+use order::is_ordered;
 
-#[proc_macro_derive(DeserializeBestEffort, attributes(serde))]
+
+#[proc_macro_derive(DeserializeBestEffort, attributes(serde, best_effort))]
 pub fn deserialize_best_effort_derive(input: TokenStream) -> TokenStream {
     // Construct a representation of Rust code as a syntax tree
     // that we can manipulate
-    let ast = syn::parse(input).unwrap();
+    let ast: syn::DeriveInput = match syn::parse(input) {
+        Ok(ast) => ast,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    // Wrong attributes are shown as compile errors at the attribute
+    if let Err(err) = check::check_input(&ast) {
+        return err.to_compile_error().into();
+    }
 
     // Build the trait implementation
     impl_deserialize_best_effort_macro(&ast)
 }
 
-fn impl_deserialize_best_effort_macro(ast: &syn::DeriveInput) -> TokenStream {
-    let name = &ast.ident;
-    let data = get_struct_data(&ast);
-    let fields = get_struct_fields(&ast);
+#[proc_macro_derive(SerializeBestEffort, attributes(serde, best_effort))]
+pub fn serialize_best_effort_derive(input: TokenStream) -> TokenStream {
+    let ast: syn::DeriveInput = match syn::parse(input) {
+        Ok(ast) => ast,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    if let Err(err) = check::check_input(&ast) {
+        return err.to_compile_error().into();
+    }
 
-    let field_enum_and_field_visitor = impl_field_enum_visitor(&fields);
-    let struct_visitor = impl_struct_visitor(&data, name);
-    let fields_array = get_fields_array(&fields);
+    serialize::impl_serialize_best_effort(&ast).into()
+}
 
-    let visitor_name = get_visitor_name(&name);
+fn impl_deserialize_best_effort_macro(ast: &syn::DeriveInput) -> TokenStream {
+    match &ast.data {
+        syn::Data::Enum(data_enum) => {
+            return enums::impl_deserialize_best_effort_enum(ast, data_enum).into();
+        },
+        syn::Data::Struct(syn::DataStruct{ fields: syn::Fields::Unnamed(fields), .. }) => {
+            return impl_deserialize_best_effort_tuple_struct(ast, fields).into();
+        },
+        syn::Data::Struct(syn::DataStruct{ fields: syn::Fields::Unit, .. }) => {
+            return impl_deserialize_best_effort_unit_struct(ast).into();
+        },
+        _ => (),
+    }
+    let name = TypeName::new(ast);
+    let fields = get_struct_fields(ast);
+    let rename_all = get_rename_all_attr(&ast.attrs);
+    let container_default = get_container_default_attr(&ast.attrs);
+
+    let visitor_name = get_visitor_name(name.ident);
+    let fields_visitor = impl_named_fields_visitor(&fields, &name, &quote!{#name}, &visitor_name, rename_all, container_default.as_ref());
+    let visitor = name.visitor_new(&visitor_name);
+    let impl_marker = name.impl_de_trait(quote!{deserialize_best_effort::DeserializeBestEffort<'de>});
+    let impl_deserialize = name.impl_de_trait(quote!{serde::de::Deserialize<'de>});
+    let impl_flatten = flatten::impl_flatten(&fields, &name, rename_all, container_default.as_ref());
     // Build impl
     let gen = quote! {
-        impl<'de> DeserializeBestEffort<'de> for #name {}
-        impl<'de> serde::de::Deserialize<'de> for #name {
+        #impl_marker {}
+        #impl_flatten
+        #impl_deserialize {
             fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
             where
                 D: serde::de::Deserializer<'de>,
             {
-                #field_enum_and_field_visitor
+                #fields_visitor
+                deserialize_best_effort::strict_result(
+                    deserializer.deserialize_struct(stringify!(#name), FIELDS, #visitor)
+                )
+            }
+        }
+    };
+    gen.into()
+}
 
-                #struct_visitor
+/// Implement the trait for newtype and tuple structs.
+/// Ex: `struct Sku(String);` or `struct Coord(f64, f64);`
+fn impl_deserialize_best_effort_tuple_struct(ast: &syn::DeriveInput, fields: &syn::FieldsUnnamed) -> proc_macro2::TokenStream {
+    let name = TypeName::new(ast);
+    let types: Vec<&syn::Type> = fields.unnamed.iter().map(|field| &field.ty).collect();
+    let len = types.len();
 
-                #fields_array
-                deserializer.deserialize_struct(stringify!(#name), FIELDS, #visitor_name)
+    let visitor_name = get_visitor_name(name.ident);
+    let tuple_visitor = impl_tuple_visitor(&types, &name, &quote!{#name}, &visitor_name);
+    let visitor = name.visitor_new(&visitor_name);
+    let deserialize_call = if len == 1 {
+        quote!{ deserializer.deserialize_newtype_struct(stringify!(#name), #visitor) }
+    } else {
+        quote!{ deserializer.deserialize_tuple_struct(stringify!(#name), #len, #visitor) }
+    };
+    let impl_marker = name.impl_de_trait(quote!{deserialize_best_effort::DeserializeBestEffort<'de>});
+    let impl_deserialize = name.impl_de_trait(quote!{serde::de::Deserialize<'de>});
+    quote! {
+        #impl_marker {}
+        #impl_deserialize {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::de::Deserializer<'de>,
+            {
+                #tuple_visitor
+                deserialize_best_effort::strict_result(#deserialize_call)
+            }
+        }
+    }
+}
 
+/// Implement the trait for unit structs.
+/// Ex: `struct Marker;`
+/// Whatever value is in the input is read and dropped.
+fn impl_deserialize_best_effort_unit_struct(ast: &syn::DeriveInput) -> proc_macro2::TokenStream {
+    let name = TypeName::new(ast);
+    let impl_marker = name.impl_de_trait(quote!{deserialize_best_effort::DeserializeBestEffort<'de>});
+    let impl_deserialize = name.impl_de_trait(quote!{serde::de::Deserialize<'de>});
+    quote! {
+        #impl_marker {}
+        #impl_deserialize {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::de::Deserializer<'de>,
+            {
+                serde::de::Deserialize::deserialize(deserializer)
+                    .map(|serde::de::IgnoredAny| #name)
             }
         }
-    };
-    gen.into()
+    }
 }
 
-fn get_alias_attrs(struct_fields: &syn::Field) -> Vec<String> {
+/// Generate the `Field` enum, the visitor and the `FIELDS` list for a list of
+/// named fields.
+/// The visitor creates the object using `constructor`, this is `Name` for a
+/// struct and `Name::Variant` for a struct variant of an enum.
+/// With `container_default` the fields start with the values of that object
+/// instead of `Default::default()`.
+fn impl_named_fields_visitor(
+    struct_fields: &[&syn::Field],
+    name: &TypeName,
+    constructor: &proc_macro2::TokenStream,
+    visitor_name: &syn::Ident,
+    rename_all: RenameRule,
+    container_default: Option<&proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream{
+    let catch_all = get_catch_all_field(struct_fields);
+
+    let field_enum_and_field_visitor = impl_field_enum_visitor(struct_fields, rename_all, catch_all);
+    let struct_visitor = impl_struct_visitor(struct_fields, name, constructor, visitor_name, rename_all, catch_all, container_default);
+    let fields_array = get_fields_array(struct_fields, rename_all, catch_all);
+
+    quote!{
+        #field_enum_and_field_visitor
+
+        #struct_visitor
+
+        #fields_array
+    }
+}
+
+/// Get the field that collects all the keys that do not match any other field.
+/// This is the field marked with `#[best_effort(catch_all)]`, or else the
+/// map field marked with `#[serde(flatten)]`.
+/// If there is no such field the unknown keys are dropped.
+/// Example
+/// ```ignore
+/// Root{
+///     field1: Vec<String>,
+///     #[best_effort(catch_all)]
+///     extra: HashMap<String, Value>,
+/// }
+/// ```
+/// Will return `Some(extra)`.
+fn get_catch_all_field<'a>(struct_fields: &[&'a syn::Field]) -> Option<&'a syn::Ident> {
+    get_catch_all_fields(struct_fields).first().map(|field| field.ident.as_ref().unwrap())
+}
+
+/// The span of the type of field `ident`, errors about the type point there.
+fn get_field_type_span(struct_fields: &[&syn::Field], ident: &syn::Ident) -> proc_macro2::Span {
+    struct_fields.iter()
+        .find(|field| field.ident.as_ref() == Some(ident))
+        .map_or_else(proc_macro2::Span::call_site, |field| field.ty.span())
+}
+
+/// All fields that could be the catch-all field, `check.rs` makes sure there
+/// is only one.
+fn get_catch_all_fields<'a>(struct_fields: &[&'a syn::Field]) -> Vec<&'a syn::Field> {
+    let marked: Vec<&syn::Field> = struct_fields.iter()
+        .filter(|field| has_best_effort_flag(&field.attrs, "catch_all"))
+        .copied()
+        .collect();
+    if marked.is_empty() {
+        struct_fields.iter()
+            .filter(|field| has_serde_flag(&field.attrs, "flatten") && is_map_type(&field.ty) && !is_skipped(field))
+            .copied()
+            .collect()
+    } else {
+        marked
+    }
+}
+
+fn is_catch_all(ident: &syn::Ident, catch_all: Option<&syn::Ident>) -> bool {
+    catch_all == Some(ident)
+}
+
+// Parse `#[serde(skip)]` and `#[serde(skip_deserializing)]`
+// The field keeps its default value, the key is read as `Field::__Skipped`
+// and the value is dropped. (So it does not end up in the catch-all.)
+fn is_skipped(struct_field: &syn::Field) -> bool {
+    has_serde_flag(&struct_field.attrs, "skip") || has_serde_flag(&struct_field.attrs, "skip_deserializing")
+}
+
+/// Is there a `Field::__Skipped`, for skipped fields and for the keys of
+/// `#[best_effort(collision = "ignore")]`.
+fn has_skipped_field(struct_fields: &[&syn::Field], catch_all: Option<&syn::Ident>) -> bool {
+    struct_fields.iter()
+        .any(|field| is_skipped(field) && !is_catch_all(field.ident.as_ref().unwrap(), catch_all))
+        || xml::has_collision(struct_fields, catch_all, xml::Collision::Ignore)
+}
+
+/// Is the value of the field read from its own key.
+fn has_own_key(struct_field: &syn::Field, catch_all: Option<&syn::Ident>) -> bool {
+    !is_catch_all(struct_field.ident.as_ref().unwrap(), catch_all) && !is_skipped(struct_field)
+        && !is_flatten_struct(struct_field, catch_all) && !is_ordered(struct_field)
+}
+
+/// Does the field have an element when the struct is given as a sequence.
+fn has_element(struct_field: &syn::Field, catch_all: Option<&syn::Ident>) -> bool {
+    !is_catch_all(struct_field.ident.as_ref().unwrap(), catch_all) && !is_skipped(struct_field)
+        && !is_ordered(struct_field)
+}
+
+/// A struct field with `#[serde(flatten)]`: its keys are in the same map as
+/// the keys of this struct. See `flatten.rs`.
+fn is_flatten_struct(struct_field: &syn::Field, catch_all: Option<&syn::Ident>) -> bool {
+    has_serde_flag(&struct_field.attrs, "flatten") && !is_map_type(&struct_field.ty)
+        && !is_skipped(struct_field) && !is_catch_all(struct_field.ident.as_ref().unwrap(), catch_all)
+}
+
+/// Where the code for the named fields is generated.
+#[derive(Clone, Copy)]
+enum FieldsIn {
+    /// `visit_map` of the visitor, the fields are local variables.
+    VisitMap,
+    /// `DeserializeBestEffortFlatten` of the struct, the fields are in `self`.
+    Flatten,
+}
+
+impl FieldsIn {
+    /// Where the value of the field is, with the span of the field so errors
+    /// about its type point there.
+    fn place(self, field: &syn::Ident) -> proc_macro2::TokenStream {
+        match self {
+            FieldsIn::VisitMap => quote!{ #field },
+            FieldsIn::Flatten => quote_spanned!{field.span()=> self.#field },
+        }
+    }
+
+    /// The flag that is set when the key of field `index` is found.
+    fn seen(self, index: usize) -> proc_macro2::TokenStream {
+        match self {
+            FieldsIn::VisitMap => {
+                let seen = get_seen_ident(index);
+                quote!{ #seen }
+            },
+            FieldsIn::Flatten => quote!{ seen.0[#index] },
+        }
+    }
+
+    /// The state of the flattened struct in field `index`, this is the
+    /// `position`th flattened struct.
+    fn flatten_seen(self, index: usize, position: usize) -> proc_macro2::TokenStream {
+        match self {
+            FieldsIn::VisitMap => {
+                let seen = get_seen_ident(index);
+                quote!{ #seen }
+            },
+            FieldsIn::Flatten => {
+                let position = syn::Index::from(position + 1);
+                quote!{ seen.#position }
+            },
+        }
+    }
+
+    /// Go to the next key.
+    fn next_key(self) -> proc_macro2::TokenStream {
+        match self {
+            FieldsIn::VisitMap => quote!{ continue },
+            FieldsIn::Flatten => quote!{ return Ok(true) },
+        }
+    }
+
+    /// The type of the object that is created.
+    fn value_type(self) -> proc_macro2::TokenStream {
+        match self {
+            FieldsIn::VisitMap => quote!{ Self::Value },
+            FieldsIn::Flatten => quote!{ Self },
+        }
+    }
+
+    /// The error type of the function the required fields are checked in.
+    fn error_type(self) -> proc_macro2::TokenStream {
+        match self {
+            FieldsIn::VisitMap => quote!{ V::Error },
+            FieldsIn::Flatten => quote!{ E },
+        }
+    }
+}
+
+/// The paths of the map types: a field with `#[serde(flatten)]` and one of
+/// these types is the catch-all field, with its own key it is a map field.
+/// A type is matched by its path, so a struct of the user named `Map` is not
+/// a map.
+const MAP_TYPES: &[&[&str]] = &[
+    &["HashMap"],
+    &["std", "collections", "HashMap"],
+    &["std", "collections", "hash_map", "HashMap"],
+    &["BTreeMap"],
+    &["std", "collections", "BTreeMap"],
+    &["std", "collections", "btree_map", "BTreeMap"],
+    &["serde_json", "Map"],
+    &["UnknownFields"],
+    &["deserialize_best_effort", "UnknownFields"],
+];
+
+/// Check if the type is one of the common map types.
+/// Ex: `HashMap<String, Value>` or `std::collections::BTreeMap<String, Value>`
+fn is_map_type(type_: &syn::Type) -> bool {
+    match type_ {
+        syn::Type::Path(syn::TypePath{ qself: None, path }) => {
+            let segments: Vec<String> = path.segments.iter().map(|segment| segment.ident.to_string()).collect();
+            MAP_TYPES.iter().any(|map_type| segments == *map_type)
+        },
+        _ => false,
+    }
+}
+
+// Parse `#[serde(flatten)]`
+fn has_serde_flag(attrs: &[syn::Attribute], flag: &str) -> bool {
+    attrs.iter()
+        .flat_map(get_serde_meta_items)
+        .any(|meta_item| is_flag(&meta_item, flag))
+}
+
+// Parse `#[best_effort(catch_all)]`
+fn has_best_effort_flag(attrs: &[syn::Attribute], flag: &str) -> bool {
+    attrs.iter()
+        .flat_map(get_best_effort_meta_items)
+        .any(|meta_item| is_flag(&meta_item, flag))
+}
+
+fn is_flag(meta_item: &syn::NestedMeta, flag: &str) -> bool {
+    match meta_item {
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) => path.is_ident(flag),
+        _ => false,
+    }
+}
+
+// Parse `#[serde(default = "path")]` on a field.
+fn get_default_fn_attr(attrs: &[syn::Attribute]) -> Option<syn::Path> {
+    let mut default_fn = None;
+    for attr in attrs{
+        for meta_item in get_serde_meta_items(attr){
+            if let Some(lit) = get_deserialize_name_value(&meta_item, "default") {
+                default_fn = lit.parse().ok();
+            }
+        }
+    }
+    default_fn
+}
+
+// Parse `#[serde(deserialize_with = "path")]` and `#[serde(with = "module")]`
+// Returns the path of the function.
+fn get_deserialize_with_attr(attrs: &[syn::Attribute]) -> Option<syn::Path> {
+    let mut deserialize_with = None;
+    for attr in attrs{
+        for meta_item in get_serde_meta_items(attr){
+            if let Some(lit) = get_deserialize_name_value(&meta_item, "deserialize_with") {
+                deserialize_with = lit.parse().ok();
+            } else if let Some(lit) = get_deserialize_name_value(&meta_item, "with") {
+                deserialize_with = lit.parse::<syn::Path>().ok()
+                    .map(|module| syn::parse_quote!{ #module::deserialize });
+            }
+        }
+    }
+    deserialize_with
+}
+
+// Parse `#[serde(default)]` and `#[serde(default = "path")]` on a struct.
+// Returns the code that creates the default object.
+fn get_container_default_attr(attrs: &[syn::Attribute]) -> Option<proc_macro2::TokenStream> {
+    if has_serde_flag(attrs, "default") {
+        return Some(quote!{ Default::default() });
+    }
+    get_default_fn_attr(attrs).map(|default_fn| quote!{ #default_fn() })
+}
+
+fn get_alias_attrs(attrs: &[syn::Attribute]) -> Vec<String> {
     let mut lit_list = Vec::new();
-    for attr in &struct_fields.attrs{
-        let tokens = get_alias_attrs_variables(&attr);
+    for attr in attrs{
+        let tokens = get_alias_attrs_variables(attr);
         for val in tokens {
             let mut string_quote = "".to_string();
             if let syn::Lit::Str(string_lit) = val {
@@ -65,7 +429,7 @@ fn get_alias_attrs(struct_fields: &syn::Field) -> Vec<String> {
 }
 
 fn get_alias_attrs_variables(attr: &syn::Attribute) -> Vec<syn::Lit> {
-    let meta_items = get_serde_meta_items(attr).unwrap();
+    let meta_items = get_serde_meta_items(attr);
     let mut lit_list: Vec<syn::Lit> = Vec::new();
     for meta_item in meta_items{
         match meta_item {
@@ -79,41 +443,120 @@ fn get_alias_attrs_variables(attr: &syn::Attribute) -> Vec<syn::Lit> {
     lit_list
 }
 
-// From https://github.com/serde-rs/serde/blob/master/serde_derive/src/internals/attr.rs line 1566
-fn get_serde_meta_items(attr: &syn::Attribute) -> Result<Vec<syn::NestedMeta>, ()> {
-    if !attr.path.is_ident("serde") {
-        return Ok(Vec::new());
+/// Get the name of the field as it appears in the input.
+/// This is the field name with `#[serde(rename = "name")]` or the container
+/// `#[serde(rename_all = "...")]` rule applied, like `serde_derive` does.
+/// Example
+/// ```ignore
+/// #[serde(rename_all = "camelCase")]
+/// struct Point{
+///     x_pos: i32,
+///     #[serde(rename = "Y")]
+///     y_pos: i32,
+/// }
+/// ```
+/// Will return `"xPos"` and `"Y"`.
+fn get_field_name(struct_field: &syn::Field, rename_all: RenameRule) -> String {
+    if let Some(rename) = get_rename_attr(&struct_field.attrs) {
+        return rename;
     }
+    let ident = struct_field.ident.as_ref().unwrap().unraw().to_string();
+    rename_all.apply_to_field(&ident)
+}
 
-    match attr.parse_meta() {
-        Ok(syn::Meta::List(meta)) => Ok(meta.nested.into_iter().collect()),
-        Ok(_other) => {
-            // cx.error_spanned_by(other, "expected #[serde(...)]");
-            Err(())
+fn get_rename_attr(attrs: &[syn::Attribute]) -> Option<String> {
+    let mut rename = None;
+    for attr in attrs{
+        for meta_item in get_serde_meta_items(attr){
+            if let Some(lit) = get_deserialize_name_value(&meta_item, "rename") {
+                rename = Some(lit.value());
+            }
         }
-        Err(_err) => {
-            // cx.syn_error(err);
-            Err(())
+    }
+    rename
+}
+
+fn get_rename_all_attr(attrs: &[syn::Attribute]) -> RenameRule {
+    let mut rename_all = RenameRule::None;
+    for attr in attrs{
+        for meta_item in get_serde_meta_items(attr){
+            if let Some(lit) = get_deserialize_name_value(&meta_item, "rename_all") {
+                if let Ok(rule) = RenameRule::from_str(&lit.value()) {
+                    rename_all = rule;
+                }
+            }
         }
     }
+    rename_all
+}
+
+/// Get the string value of an attribute that can be set for both directions.
+/// Parse `#[serde(rename = "foo")]` and `#[serde(rename(deserialize = "foo"))]`,
+/// `#[serde(rename(serialize = "foo"))]` is ignored.
+fn get_deserialize_name_value(meta_item: &syn::NestedMeta, name: &str) -> Option<syn::LitStr> {
+    match meta_item {
+        // Parse `#[serde(rename = "foo")]`
+        syn::NestedMeta::Meta(syn::Meta::NameValue(m)) if m.path.is_ident(name) => {
+            match &m.lit {
+                syn::Lit::Str(lit) => Some(lit.clone()),
+                _ => None,
+            }
+        },
+        // Parse `#[serde(rename(serialize = "foo", deserialize = "bar"))]`
+        syn::NestedMeta::Meta(syn::Meta::List(m)) if m.path.is_ident(name) => {
+            let mut value = None;
+            for nested in &m.nested {
+                if let syn::NestedMeta::Meta(syn::Meta::NameValue(m)) = nested {
+                    if let (true, syn::Lit::Str(lit)) = (m.path.is_ident("deserialize"), &m.lit) {
+                        value = Some(lit.clone());
+                    }
+                }
+            }
+            value
+        },
+        _ => None,
+    }
+}
+
+// An attribute that can not be parsed is reported by `check.rs`.
+fn get_serde_meta_items(attr: &syn::Attribute) -> Vec<syn::NestedMeta> {
+    get_meta_items(attr, "serde").unwrap_or_default()
+}
+
+fn get_best_effort_meta_items(attr: &syn::Attribute) -> Vec<syn::NestedMeta> {
+    get_meta_items(attr, "best_effort").unwrap_or_default()
+}
+
+// From https://github.com/serde-rs/serde/blob/master/serde_derive/src/internals/attr.rs line 1566
+fn get_meta_items(attr: &syn::Attribute, attr_name: &str) -> syn::Result<Vec<syn::NestedMeta>> {
+    if !attr.path.is_ident(attr_name) {
+        return Ok(Vec::new());
+    }
+
+    match attr.parse_meta()? {
+        syn::Meta::List(meta) => Ok(meta.nested.into_iter().collect()),
+        other => Err(syn::Error::new_spanned(other, format!("expected #[{}(...)]", attr_name))),
+    }
 }
 
-fn get_fields_array(struct_fields: &[&syn::Field]) -> proc_macro2::TokenStream{
+fn get_fields_array(struct_fields: &[&syn::Field], rename_all: RenameRule, catch_all: Option<&syn::Ident>) -> proc_macro2::TokenStream{
     let mut parse_gen = quote!{};
-    for (i, field) in struct_fields.iter().enumerate(){
-        // parse normal name (name of variable)
+    for field in struct_fields{
+        if !has_own_key(field, catch_all) {
+            continue;
+        }
+        // parse normal name (name of variable, after `rename`/`rename_all`)
         // Ex: `pub name: String,` will add `"name",` to the list
-        let ident = field.ident.as_ref().unwrap();
-        let field_ident = get_enum_ident(ident,i);
+        let field_name = get_field_name(field, rename_all);
         parse_gen = quote!{
-            #parse_gen stringify!(#field_ident),
+            #parse_gen #field_name,
         };
         // parse alias names (if any)
         // Ex: `#[serde(alias = "type")]` will add `"type",` to the list
-        let alias_ident = get_alias_attrs(field);
+        let alias_ident = get_alias_attrs(&field.attrs);
         for alias in alias_ident{
             parse_gen = quote!{
-                #parse_gen stringify!(#alias),
+                #parse_gen #alias,
             };
         }
     }
@@ -123,62 +566,110 @@ fn get_fields_array(struct_fields: &[&syn::Field]) -> proc_macro2::TokenStream{
     parse_gen
 }
 
-fn get_field_enum(struct_fields: &[&syn::Field]) -> proc_macro2::TokenStream{
+/// The `Field` enum and `Field::from_key`, that finds the field of a key.
+fn get_field_enum(struct_fields: &[&syn::Field], rename_all: RenameRule, catch_all: Option<&syn::Ident>) -> proc_macro2::TokenStream{
     let mut parse_gen = quote!{};
-    for (i, field) in struct_fields.iter().enumerate(){
+    for field in struct_fields{
         let ident = field.ident.as_ref().unwrap();
-        let field_ident = get_enum_ident(ident,i);
+        if !has_own_key(field, catch_all) {
+            continue;
+        }
+        let field_ident = get_enum_ident(ident);
         parse_gen = quote!{
             #parse_gen
             #field_ident,
         }
     }
+    if has_skipped_field(struct_fields, catch_all) {
+        parse_gen = quote!{
+            #parse_gen
+            __Skipped(&'static str),
+        }
+    }
+    // The key and the error message of `#[best_effort(collision = "error")]`.
+    if xml::has_collision(struct_fields, catch_all, xml::Collision::Error) {
+        parse_gen = quote!{
+            #parse_gen
+            __Collision(&'static str, &'static str),
+        }
+    }
+    let field_enum_match = get_field_enum_match(struct_fields, rename_all, catch_all);
     parse_gen = quote!{
+        // The helper variants start with `__`, so they can not be the same as
+        // a field of the struct. A variant for a collision is not used when
+        // there is a field of the other kind.
+        #[allow(non_camel_case_types, dead_code)]
         enum Field {
             #parse_gen
-            Unknown(String),
+            __Unknown(String),
+        }
+
+        impl Field {
+            /// A key that starts with `@` is an XML attribute.
+            fn from_key(value: &str) -> Field {
+                #field_enum_match
+            }
         }
     };
     parse_gen
 }
-fn get_field_enum_match(struct_fields: &[&syn::Field]) -> proc_macro2::TokenStream{
+/// Without `#[best_effort(attribute)]` or `#[best_effort(element)]` fields the
+/// kind of the key is not used, see `xml.rs`.
+fn get_field_enum_match(struct_fields: &[&syn::Field], rename_all: RenameRule, catch_all: Option<&syn::Ident>) -> proc_macro2::TokenStream{
+    let mut arms = Vec::new();
     let mut parse_gen = quote!{};
-    for(i, field) in struct_fields.iter().enumerate(){
+    for field in struct_fields{
         let ident = field.ident.as_ref().unwrap();
-        let field_enum_ident = get_enum_ident(ident,i);
+        if is_catch_all(ident, catch_all) || is_flatten_struct(field, catch_all) || is_ordered(field) {
+            continue;
+        }
+        // from `#[serde(rename = "id")]` or `#[serde(rename_all = "...")]`
+        let field_name = get_field_name(field, rename_all);
+        // The key of a skipped field is kept for `#[best_effort(ordered)]`.
+        let field_enum_ident = if is_skipped(field) {
+            quote!{ __Skipped(#field_name) }
+        } else {
+            let field_enum_ident = get_enum_ident(ident);
+            quote!{ #field_enum_ident }
+        };
         parse_gen = quote!{
             #parse_gen
-            // "id" => Ok(Field::Id),
-            stringify!(#ident) => Ok(Field::#field_enum_ident),
+            // "id" => Field::id,
+            #field_name => Field::#field_enum_ident,
         };
         // from #[serde(alias = "type")]
-        let alias_ident = get_alias_attrs(field);
+        let alias_ident = get_alias_attrs(&field.attrs);
 
 
         for alias in alias_ident{
             parse_gen = quote!{
                 #parse_gen
-                // "type" => Ok(Field::Id),
-                #alias => Ok(Field::#field_enum_ident),
+                // "type" => Field::id,
+                #alias => Field::#field_enum_ident,
             };
         }
+        arms.push((*field, field_enum_ident));
     }
+    if xml::has_kind_fields(struct_fields, catch_all) {
+        return xml::get_field_enum_match(struct_fields, rename_all, catch_all, &arms);
+    }
+    // Without marked fields an `@name` key is not a field, it goes to the
+    // catch-all field.
     parse_gen = quote!{
         match value {
-            // "id" => Ok(Field::Id),
+            // "id" => Field::id,
             #parse_gen
-            _ => Ok(Field::Unknown(value.to_string())),
+            _ => Field::__Unknown(value.to_string()),
         }
     };
     parse_gen
 }
 
-fn impl_field_enum_visitor(struct_fields: &[&syn::Field]) -> proc_macro2::TokenStream{
-    let field_enum = get_field_enum(&struct_fields);
-    let field_enum_match = get_field_enum_match(&struct_fields);
+fn impl_field_enum_visitor(struct_fields: &[&syn::Field], rename_all: RenameRule, catch_all: Option<&syn::Ident>) -> proc_macro2::TokenStream{
+    let field_enum = get_field_enum(struct_fields, rename_all, catch_all);
 
     let parse_gen = quote!{
-        // enum Field { Id, Name, Race, Unknown, Special };
+        // enum Field { id, name, race, __Unknown(String) };
         #field_enum
 
         impl<'de> serde::de::Deserialize<'de> for Field {
@@ -192,14 +683,14 @@ fn impl_field_enum_visitor(struct_fields: &[&syn::Field]) -> proc_macro2::TokenS
                     type Value = Field;
 
                     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                        formatter.write_str("Did not expect this... se default is not working.")
+                        formatter.write_str("field identifier")
                     }
 
                     fn visit_str<E>(self, value: &str) -> Result<Field, E>
                     where
                         E: de::Error,
                     {
-                        #field_enum_match
+                        Ok(Field::from_key(value))
                     }
                 }
                 // deserialize_any
@@ -211,20 +702,32 @@ fn impl_field_enum_visitor(struct_fields: &[&syn::Field]) -> proc_macro2::TokenS
 }
 
 
-fn impl_struct_visitor(struct_data: &[(&syn::Ident, &syn::Type)], name: &syn::Ident) -> proc_macro2::TokenStream{
-    let visit_seq = get_struct_visit_seq(&struct_data, &name);
-    let visit_map = get_struct_visit_map(&struct_data, &name);
+fn impl_struct_visitor(
+    struct_fields: &[&syn::Field],
+    name: &TypeName,
+    constructor: &proc_macro2::TokenStream,
+    visitor_name: &syn::Ident,
+    rename_all: RenameRule,
+    catch_all: Option<&syn::Ident>,
+    container_default: Option<&proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream{
+    let visit_seq = get_struct_visit_seq(struct_fields, constructor, catch_all, container_default);
+    let visit_map = get_struct_visit_map(struct_fields, constructor, rename_all, catch_all, container_default);
 
-    let visitor_name = get_visitor_name(&name);
+    let visitor_struct = name.visitor_struct(visitor_name);
+    let visitor_impl = name.visitor_impl(visitor_name);
+    let name_ty = name.ty();
+    // Ex: "struct Point" or "struct variant Shape::Rect"
+    let expecting = get_expecting("struct", constructor);
 
     let parse_gen = quote!{
-        struct #visitor_name;
+        #visitor_struct
 
-        impl<'de> serde::de::Visitor<'de> for #visitor_name {
-            type Value = #name;
+        #visitor_impl {
+            type Value = #name_ty;
 
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str(&format!("struct {}",stringify!(#visitor_name)))
+                formatter.write_str(#expecting)
             }
 
             #visit_seq
@@ -237,20 +740,50 @@ fn impl_struct_visitor(struct_data: &[(&syn::Ident, &syn::Type)], name: &syn::Id
     parse_gen
 }
 
-fn get_struct_visit_seq(struct_data: &[(&syn::Ident, &syn::Type)], name: &syn::Ident) -> proc_macro2::TokenStream{
+/// Formats like bincode, CSV or a JSON array give a struct as a sequence:
+/// the values of the fields in order, without keys.
+/// Elements that are missing or can not be parsed keep the default value of
+/// the field (a missing `#[best_effort(required)]` field is an error).
+/// Skipped fields and the catch-all field do not have an element, elements
+/// after the last field go to the catch-all field with their index as key.
+fn get_struct_visit_seq(
+    struct_fields: &[&syn::Field],
+    constructor: &proc_macro2::TokenStream,
+    catch_all: Option<&syn::Ident>,
+    container_default: Option<&proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream{
+    let struct_data = get_fields_data(struct_fields);
 
-    let variable_init = set_struct_visit_seq_variable(&struct_data);
-    let create_object = set_struct_create_object(&struct_data, &name);
+    let variable_init = set_struct_visit_map_variable(struct_fields, catch_all, container_default, FieldsIn::VisitMap);
+    let elements = set_struct_visit_seq_elements(struct_fields, catch_all);
+    let len = struct_fields.iter().filter(|field| has_element(field, catch_all)).count();
+    let extra_elements = match catch_all {
+        Some(catch_all) => {
+            let add_data_at = quote_spanned!{get_field_type_span(struct_fields, catch_all)=>
+                deserialize_best_effort::DeserializeBestEffortTypes::add_data_at(&mut #catch_all, &__index.to_string(), __next_value, __index);
+            };
+            quote!{
+                let mut __index = #len;
+                while let Some(Some(__next_value)) = deserialize_best_effort::next_element_or_report(&mut __seq, __index) {
+                    #add_data_at
+                    __index += 1;
+                }
+            }
+        },
+        None => quote!{
+            deserialize_best_effort::skip_remaining_elements(&mut __seq, #len, &self)?;
+        },
+    };
+    let create_object = set_struct_create_object(&struct_data, constructor);
 
-    // This code is not used for structs
-    // TODO: Test this code if it works
     let parse_gen = quote!{
-        fn visit_seq<V>(self, mut seq: V) -> Result<#name, V::Error>
+        fn visit_seq<V>(self, mut __seq: V) -> Result<Self::Value, V::Error>
         where
             V: serde::de::SeqAccess<'de>,
         {
-            // not used for struct it seems
             #variable_init
+            #elements
+            #extra_elements
             Ok(#create_object)
         }
 
@@ -258,39 +791,91 @@ fn get_struct_visit_seq(struct_data: &[(&syn::Ident, &syn::Type)], name: &syn::I
     parse_gen
 }
 
-fn set_struct_visit_seq_variable(struct_data: &[(&syn::Ident, &syn::Type)]) -> proc_macro2::TokenStream{
+fn set_struct_visit_seq_elements(struct_fields: &[&syn::Field], catch_all: Option<&syn::Ident>) -> proc_macro2::TokenStream{
     let mut parse_gen = quote!{};
-    for (i, (field,type_)) in struct_data.iter().enumerate(){
+    let element_fields = struct_fields.iter().filter(|field| has_element(field, catch_all));
+    for (i, struct_field) in element_fields.enumerate(){
+        let field = struct_field.ident.as_ref().unwrap();
+        let (next_element, apply) = match get_deserialize_with_attr(&struct_field.attrs) {
+            Some(deserialize_with) => (
+                quote!{ deserialize_best_effort::next_element_with(&mut __seq, #i, #deserialize_with) },
+                MergeStrategy::from_attrs(&struct_field.attrs).apply(struct_field, &quote!{ #field }),
+            ),
+            None => (
+                quote!{ deserialize_best_effort::next_element_or_report(&mut __seq, #i) },
+                quote!{ #field = __next_value; },
+            ),
+        };
+        let (invalid, missing) = if has_best_effort_flag(&struct_field.attrs, "required") {
+            (
+                invalid_required_field(&field.unraw().to_string()),
+                quote!{ return Err(serde::de::Error::invalid_length(#i, &self)); },
+            )
+        } else {
+            (quote!{}, quote!{})
+        };
         parse_gen = quote!{
             #parse_gen
             // Example of generated code here:
-            // let id = seq.next_element()?
-            //     .ok_or_else(|| de::Error::invalid_length(0, &self))?;
-            let #field:#type_ = seq.next_element()?
-                .ok_or_else(|| de::Error::invalid_length(#i, &self))?;
+            // match deserialize_best_effort::next_element_or_report(&mut __seq, 0) {
+            //     Some(Some(__next_value)) => { id = __next_value; },
+            //     Some(None) => (),
+            //     None => (),
+            // }
+            match #next_element {
+                Some(Some(__next_value)) => { #apply },
+                Some(None) => { #invalid },
+                None => { #missing },
+            }
         };
     }
     parse_gen
 }
 
-fn get_struct_visit_map(struct_data: &[(&syn::Ident, &syn::Type)], name: &syn::Ident) -> proc_macro2::TokenStream{
+fn get_struct_visit_map(
+    struct_fields: &[&syn::Field],
+    constructor: &proc_macro2::TokenStream,
+    rename_all: RenameRule,
+    catch_all: Option<&syn::Ident>,
+    container_default: Option<&proc_macro2::TokenStream>,
+) -> proc_macro2::TokenStream{
+    let struct_data = get_fields_data(struct_fields);
 
-    let variable_init = set_struct_visit_map_variable(&struct_data);
-    let enum_match_variable = set_struct_visit_map_enum_match(&struct_data);
-    let create_object = set_struct_create_object(&struct_data, &name);
+    let variable_init = set_struct_visit_map_variable(struct_fields, catch_all, container_default, FieldsIn::VisitMap);
+    let seen_init = set_struct_visit_map_seen(struct_fields, catch_all);
+    let enum_match_variable = set_struct_visit_map_enum_match(struct_fields, rename_all, catch_all, container_default, FieldsIn::VisitMap);
+    let required_check = set_struct_visit_map_required_check(struct_fields, rename_all, catch_all, FieldsIn::VisitMap);
+    let record_key = order::record_key(struct_fields, rename_all, catch_all);
+    // `__position` is the number of keys before `__key`, for the catch-all field.
+    let (position_init, position_next) = if catch_all.is_some() || !get_flatten_fields(struct_fields, catch_all).is_empty() {
+        (
+            quote!{ let mut __next_position: usize = 0; },
+            quote!{
+                let __position = __next_position;
+                __next_position += 1;
+            },
+        )
+    } else {
+        (quote!{}, quote!{})
+    };
+    let create_object = set_struct_create_object(&struct_data, constructor);
 
     let parse_gen = quote!{
-        fn visit_map<V>(self, mut map: V) -> Result<#name, V::Error>
+        fn visit_map<V>(self, mut __map: V) -> Result<Self::Value, V::Error>
         where
             V: serde::de::MapAccess<'de>,
         {
 
             #variable_init
+            #seen_init
 
-            while let Some(key) = map.next_key()? {
-
+            #position_init
+            while let Some(__key) = __map.next_key()? {
+                #position_next
+                #record_key
                 #enum_match_variable
             }
+            #required_check
             Ok(#create_object)
         }
 
@@ -299,7 +884,7 @@ fn get_struct_visit_map(struct_data: &[(&syn::Ident, &syn::Type)], name: &syn::I
 
 }
 
-fn set_struct_create_object(struct_data: &[(&syn::Ident, &syn::Type)], name: &syn::Ident) -> proc_macro2::TokenStream{
+fn set_struct_create_object(struct_data: &[(&syn::Ident, &syn::Type)], constructor: &proc_macro2::TokenStream) -> proc_macro2::TokenStream{
     let mut parse_gen = quote!{};
     for (field,_type) in struct_data{
         parse_gen = quote!{
@@ -311,7 +896,7 @@ fn set_struct_create_object(struct_data: &[(&syn::Ident, &syn::Type)], name: &sy
     }
     parse_gen = quote!{
         //#[allow(clippy::needless_update)]
-        #name{
+        #constructor{
             #parse_gen
             //..Default::default()
         }
@@ -319,50 +904,375 @@ fn set_struct_create_object(struct_data: &[(&syn::Ident, &syn::Type)], name: &sy
     parse_gen
 }
 
-fn set_struct_visit_map_variable(struct_data: &[(&syn::Ident, &syn::Type)]) -> proc_macro2::TokenStream{
-    let mut parse_gen = quote!{};
-    for (field,type_) in struct_data{
+/// A flattened struct starts with `flatten_default`, so its own defaults are
+/// used.
+fn set_struct_visit_map_variable(
+    struct_fields: &[&syn::Field],
+    catch_all: Option<&syn::Ident>,
+    container_default: Option<&proc_macro2::TokenStream>,
+    fields_in: FieldsIn,
+) -> proc_macro2::TokenStream{
+    let mut parse_gen = match container_default {
+        Some(container_default) => {
+            let value_type = fields_in.value_type();
+            quote!{
+                let __default: #value_type = #container_default;
+            }
+        },
+        None => quote!{},
+    };
+    for struct_field in struct_fields{
+        let field = struct_field.ident.as_ref().unwrap();
+        let type_ = &struct_field.ty;
+        let default_value = match (get_default_fn_attr(&struct_field.attrs), container_default) {
+            (Some(default_fn), _) => quote!{ #default_fn() },
+            (None, Some(_)) => quote!{ __default.#field },
+            (None, None) if is_flatten_struct(struct_field, catch_all) => quote!{
+                <#type_ as deserialize_best_effort::DeserializeBestEffortFlatten<'de>>::flatten_default()
+            },
+            (None, None) => quote!{ Default::default() },
+        };
         parse_gen = quote!{
             #parse_gen
             // Example of generated code here:
             //let mut id:i32 = Default::default();
-            let mut #field:#type_ = Default::default();
+            let mut #field:#type_ = #default_value;
         }
     }
     parse_gen
 }
 
-fn set_struct_visit_map_enum_match(struct_data: &[(&syn::Ident, &syn::Type)]) -> proc_macro2::TokenStream{
+/// Declare the `seen_fieldN` flags, and the state of the flattened structs.
+fn set_struct_visit_map_seen(struct_fields: &[&syn::Field], catch_all: Option<&syn::Ident>) -> proc_macro2::TokenStream{
     let mut parse_gen = quote!{};
-    for (i, (field,_type)) in struct_data.iter().enumerate(){
-        let field_ident = get_enum_ident(field, i);
+    for (i, struct_field) in struct_fields.iter().enumerate(){
+        let seen = get_seen_ident(i);
+        let type_ = &struct_field.ty;
+        if is_flatten_struct(struct_field, catch_all) {
+            parse_gen = quote!{
+                #parse_gen
+                let mut #seen = <#type_ as deserialize_best_effort::DeserializeBestEffortFlatten<'de>>::flatten_seen();
+            };
+        } else if needs_seen(struct_field, catch_all) {
+            parse_gen = quote!{
+                #parse_gen
+                let mut #seen = false;
+            };
+        }
+    }
+    parse_gen
+}
+
+/// `#[best_effort(required)]` fields return `missing_field` when their key
+/// was not found. Flattened structs check their own fields.
+fn set_struct_visit_map_required_check(
+    struct_fields: &[&syn::Field],
+    rename_all: RenameRule,
+    catch_all: Option<&syn::Ident>,
+    fields_in: FieldsIn,
+) -> proc_macro2::TokenStream{
+    let mut parse_gen = quote!{};
+    for (i, struct_field) in struct_fields.iter().enumerate(){
+        if !has_best_effort_flag(&struct_field.attrs, "required") || is_skipped(struct_field)
+            || is_flatten_struct(struct_field, catch_all) {
+            continue;
+        }
+        let field_name = get_field_name(struct_field, rename_all);
+        let seen = fields_in.seen(i);
+        parse_gen = quote!{
+            #parse_gen
+            if !#seen {
+                return Err(serde::de::Error::missing_field(#field_name));
+            }
+        };
+    }
+    let error_type = fields_in.error_type();
+    for (position, (i, struct_field)) in get_flatten_fields(struct_fields, catch_all).into_iter().enumerate(){
+        let place = fields_in.place(struct_field.ident.as_ref().unwrap());
+        let seen = fields_in.flatten_seen(i, position);
+        parse_gen = quote!{
+            #parse_gen
+            deserialize_best_effort::DeserializeBestEffortFlatten::flatten_end::<#error_type>(&#place, &#seen)?;
+        };
+    }
+    parse_gen
+}
+
+/// A `#[best_effort(required)]` field with a value that can not be parsed is
+/// an error, the field would have its default value otherwise.
+fn invalid_required_field(field_name: &str) -> proc_macro2::TokenStream {
+    let message = format!("invalid value for required field `{}`", field_name);
+    quote!{ return Err(serde::de::Error::custom(#message)); }
+}
+
+/// Required fields and most merge strategies need to know if the key was found.
+fn needs_seen(struct_field: &syn::Field, catch_all: Option<&syn::Ident>) -> bool {
+    has_own_key(struct_field, catch_all) && (has_best_effort_flag(&struct_field.attrs, "required")
+        || MergeStrategy::from_attrs(&struct_field.attrs).tracks_seen())
+}
+
+/// The fields with `#[serde(flatten)]` that are structs, with their index.
+fn get_flatten_fields<'a>(struct_fields: &[&'a syn::Field], catch_all: Option<&syn::Ident>) -> Vec<(usize, &'a syn::Field)> {
+    struct_fields.iter()
+        .enumerate()
+        .filter(|(_, field)| is_flatten_struct(field, catch_all))
+        .map(|(i, field)| (i, *field))
+        .collect()
+}
+
+/// Fields with a default function or a container default do not need
+/// `Default`, a value that can not be parsed leaves the field as it is.
+/// Unknown keys are given to the flattened structs first, then to the
+/// catch-all field.
+fn set_struct_visit_map_enum_match(
+    struct_fields: &[&syn::Field],
+    rename_all: RenameRule,
+    catch_all: Option<&syn::Ident>,
+    container_default: Option<&proc_macro2::TokenStream>,
+    fields_in: FieldsIn,
+) -> proc_macro2::TokenStream{
+    let next_key = fields_in.next_key();
+    let mut parse_gen = quote!{};
+    for (i, struct_field) in struct_fields.iter().enumerate(){
+        let field = struct_field.ident.as_ref().unwrap();
+        if !has_own_key(struct_field, catch_all) {
+            continue;
+        }
+        let field_ident = get_enum_ident(field);
+        let default_fn = get_default_fn_attr(&struct_field.attrs);
+        let keep_value = default_fn.is_some() || container_default.is_some();
+        let required = has_best_effort_flag(&struct_field.attrs, "required");
+        let field_name = get_field_name(struct_field, rename_all);
+        let next_value = match (get_deserialize_with_attr(&struct_field.attrs), keep_value) {
+            // A required field is only there if its value can be read.
+            (None, _) if required => quote!{
+                deserialize_best_effort::next_value_required(&mut __map, stringify!(#field))?
+            },
+            (Some(deserialize_with), _) => {
+                let on_error = if required {
+                    invalid_required_field(&field_name)
+                } else if keep_value {
+                    next_key.clone()
+                } else {
+                    quote!{ Default::default() }
+                };
+                quote!{
+                    match deserialize_best_effort::next_value_with(&mut __map, stringify!(#field), #deserialize_with) {
+                        Some(__next_value) => __next_value,
+                        None => #on_error,
+                    }
+                }
+            },
+            (None, true) => quote!{
+                match deserialize_best_effort::next_value_or_report(&mut __map, stringify!(#field)) {
+                    Some(__next_value) => __next_value,
+                    None => #next_key,
+                }
+            },
+            (None, false) => quote!{
+                deserialize_best_effort::next_value_or_default(&mut __map, stringify!(#field))
+            },
+        };
+        // A map field reads the entries of the map, `MergeStrategy::apply`
+        // adds them one by one.
+        let next_value = if is_map_type(&struct_field.ty) && get_deserialize_with_attr(&struct_field.attrs).is_none() {
+            quote!{ {
+                let __entries: deserialize_best_effort::MapEntries<_> = #next_value;
+                __entries
+            } }
+        } else {
+            next_value
+        };
+        // The value the field starts with, see `set_struct_visit_map_variable`.
+        let reset = match (default_fn, container_default) {
+            (Some(default_fn), _) => quote!{ #default_fn() },
+            (None, Some(container_default)) => {
+                let value_type = fields_in.value_type();
+                quote!{ {
+                    let __default: #value_type = #container_default;
+                    __default.#field
+                } }
+            },
+            (None, None) => quote!{ Default::default() },
+        };
+        let merge_strategy = MergeStrategy::from_attrs(&struct_field.attrs);
+        let seen = fields_in.seen(i);
+        let mark_seen = if needs_seen(struct_field, catch_all) && !merge_strategy.tracks_seen() {
+            quote!{ #seen = true; }
+        } else {
+            quote!{}
+        };
+        let merge = merge_strategy.match_arm(struct_field, &field_name, &fields_in.place(field), &seen, &next_value, &reset);
         parse_gen = quote!{
             #parse_gen
             // Example of generated code here:
-            // Field::Enum_id => {
-            //     let next_value = map.next_value().unwrap_or_default();
-            //     id.add_data(next_value);
+            // Field::id => {
+            //     let next_value = deserialize_best_effort::next_value_or_default(&mut __map, "id");
+            //     id.add_data("id", next_value);
             // }
+            // `#[best_effort(merge = "...")]` changes the body, see `merge.rs`.
             Field::#field_ident => {
-                let next_value = map.next_value().unwrap_or_default();
-                #field.add_data(stringify!(#field), next_value);
+                #mark_seen
+                #merge
             }
         }
     }
-    parse_gen = quote!{
-        match key {
+    if has_skipped_field(struct_fields, catch_all) {
+        parse_gen = quote!{
             #parse_gen
-            Field::Unknown(key_name) => {
-                let next_value = map.next_value().unwrap_or_default();
-                unknown.add_data(&key_name, next_value);
+            Field::__Skipped(_) => {
+                let _ = __map.next_value::<serde::de::IgnoredAny>();
+            }
+        }
+    }
+    if xml::has_collision(struct_fields, catch_all, xml::Collision::Error) {
+        parse_gen = quote!{
+            #parse_gen
+            Field::__Collision(_, __message) => {
+                return Err(serde::de::Error::custom(__message));
+            }
+        }
+    }
+    let flatten_fields = get_flatten_fields(struct_fields, catch_all);
+    let mut flatten_match = quote!{};
+    for (position, (i, struct_field)) in flatten_fields.iter().enumerate(){
+        let place = fields_in.place(struct_field.ident.as_ref().unwrap());
+        let seen = fields_in.flatten_seen(*i, position);
+        flatten_match = quote!{
+            #flatten_match
+            if deserialize_best_effort::DeserializeBestEffortFlatten::flatten_next_value(&mut #place, &mut #seen, &__key_name, __position, &mut __map)? {
+                #next_key;
+            }
+        };
+    }
+    let fallback = match (catch_all, fields_in) {
+        (Some(catch_all), _) => {
+            let place = fields_in.place(catch_all);
+            let add_data_at = quote_spanned!{get_field_type_span(struct_fields, catch_all)=>
+                deserialize_best_effort::DeserializeBestEffortTypes::add_data_at(&mut #place, &__key_name, __next_value, __position);
+            };
+            quote!{
+                let __next_value = deserialize_best_effort::next_value_or_default(&mut __map, &__key_name);
+                #add_data_at
             }
+        },
+        // No catch-all field, so the value is read and dropped (in strict
+        // mode it is an error).
+        (None, FieldsIn::VisitMap) => quote!{
+            if deserialize_best_effort::is_strict() {
+                return Err(serde::de::Error::unknown_field(&__key_name, FIELDS));
+            }
+            let _ = __map.next_value::<serde::de::IgnoredAny>();
+        },
+        // The key is not for this struct, the struct that it is flattened
+        // into uses it.
+        (None, FieldsIn::Flatten) => quote!{
+            return Ok(false);
+        },
+    };
+    let key_name = if catch_all.is_some() || !flatten_fields.is_empty() || matches!(fields_in, FieldsIn::VisitMap) {
+        quote!{ __key_name }
+    } else {
+        quote!{ _ }
+    };
+    let unknown_match = quote!{
+        Field::__Unknown(#key_name) => {
+            #flatten_match
+            #fallback
+        }
+    };
+    parse_gen = quote!{
+        match __key {
+            #parse_gen
+            #unknown_match
         }
     };
     parse_gen
 }
 
-fn get_enum_ident(_ident: &syn::Ident, nummer: usize) -> syn::Ident{
-    format_ident!("Enum{}", nummer)
+/// Generate a visitor for unnamed fields, like in a tuple struct or a tuple variant.
+/// Elements that are missing or can not be parsed fall back to `Default`,
+/// just like named fields do in `visit_map`.
+/// Elements after the last field are read and dropped (in strict mode they
+/// are an error).
+/// With only one field the visitor also accepts a newtype struct.
+fn impl_tuple_visitor(
+    types: &[&syn::Type],
+    name: &TypeName,
+    constructor: &proc_macro2::TokenStream,
+    visitor_name: &syn::Ident,
+) -> proc_macro2::TokenStream{
+    let mut variable_init = quote!{};
+    let mut values = quote!{};
+    for (i, type_) in types.iter().enumerate(){
+        let field = format_ident!("field{}", i);
+        variable_init = quote!{
+            #variable_init
+            // Example of generated code here:
+            // let field0:f64 = deserialize_best_effort::next_element_or_default(&mut seq, 0);
+            let #field:#type_ = deserialize_best_effort::next_element_or_default(&mut seq, #i);
+        };
+        values = quote!{
+            #values #field,
+        };
+    }
+    let visit_newtype_struct = if types.len() == 1 {
+        quote!{
+            fn visit_newtype_struct<E>(self, deserializer: E) -> Result<Self::Value, E::Error>
+            where
+                E: serde::de::Deserializer<'de>,
+            {
+                let field0 = deserialize_best_effort::deserialize_or_default(deserializer, "0");
+                Ok(#constructor(field0))
+            }
+        }
+    } else {
+        quote!{}
+    };
+    let visitor_struct = name.visitor_struct(visitor_name);
+    let visitor_impl = name.visitor_impl(visitor_name);
+    let name_ty = name.ty();
+    let expecting = get_expecting("tuple", constructor);
+    let len = types.len();
+    quote!{
+        #visitor_struct
+
+        #visitor_impl {
+            type Value = #name_ty;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str(#expecting)
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: serde::de::SeqAccess<'de>,
+            {
+                #variable_init
+                deserialize_best_effort::skip_remaining_elements(&mut seq, #len, &self)?;
+                Ok(#constructor(#values))
+            }
+
+            #visit_newtype_struct
+        }
+    }
+}
+
+/// The variant of the `Field` or `Variant` enum, this is the name of the field
+/// or variant itself. Ex: `Field::id` or `Variant::Card`
+fn get_enum_ident(ident: &syn::Ident) -> syn::Ident{
+    ident.clone()
+}
+
+/// What the visitor expects, for error messages.
+/// `constructor` is `Name` for a struct and `Name::Variant` for a variant.
+fn get_expecting(kind: &str, constructor: &proc_macro2::TokenStream) -> String {
+    let constructor = constructor.to_string().replace(' ', "");
+    if constructor.contains("::") {
+        format!("{} variant {}", kind, constructor)
+    } else {
+        format!("{} {}", kind, constructor)
+    }
 }
 
 fn get_visitor_name(ident: &syn::Ident) -> syn::Ident{
@@ -378,44 +1288,30 @@ fn get_visitor_name(ident: &syn::Ident) -> syn::Ident{
 /// }
 /// ```
 /// Will return `["x","y"]` but then as an `syn::Ident`
-fn get_struct_data(ast: &syn::DeriveInput)-> Vec<(&syn::Ident, &syn::Type)> {
-    let mut list = Vec::new();
-    match &ast.data{
-        syn::Data::Struct(x) => {
-            match &x.fields {
-                syn::Fields::Named(x) => {
-                    for field in &x.named {
-                        list.push((field.ident.as_ref().unwrap(), &field.ty));
-                    }
-                    Some(())
-                },
-                syn::Fields::Unnamed(_) => None,
-                syn::Fields::Unit => None,
-            }
-        },
-        syn::Data::Enum(_x) => None,
-        syn::Data::Union(_x) => None,
-    };
-    list
+fn get_fields_data<'a>(struct_fields: &[&'a syn::Field])-> Vec<(&'a syn::Ident, &'a syn::Type)> {
+    struct_fields.iter()
+        .map(|field| (field.ident.as_ref().unwrap(), &field.ty))
+        .collect()
 }
 
 fn get_struct_fields(ast: &syn::DeriveInput)-> Vec<&syn::Field> {
-    let mut list = Vec::new();
     match &ast.data{
-        syn::Data::Struct(x) => {
-            match &x.fields {
-                syn::Fields::Named(x) => {
-                    for field in &x.named {
-                        list.push(field);
-                    }
-                    Some(())
-                },
-                syn::Fields::Unnamed(_) => None,
-                syn::Fields::Unit => None,
+        syn::Data::Struct(x) => get_named_fields(&x.fields),
+        syn::Data::Enum(_x) => Vec::new(),
+        syn::Data::Union(_x) => Vec::new(),
+    }
+}
+
+fn get_named_fields(fields: &syn::Fields)-> Vec<&syn::Field> {
+    let mut list = Vec::new();
+    match fields {
+        syn::Fields::Named(x) => {
+            for field in &x.named {
+                list.push(field);
             }
         },
-        syn::Data::Enum(_x) => None,
-        syn::Data::Union(_x) => None,
+        syn::Fields::Unnamed(_) => (),
+        syn::Fields::Unit => (),
     };
     list
 }
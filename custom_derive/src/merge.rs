@@ -0,0 +1,154 @@
+//! What to do when a key is found more than once.
+//!
+//! By default the value is given to `add_data`, so the type decides:
+//! scalars keep the last value, `Vec` appends and `Option` overwrites.
+//! `#[best_effort(merge = "...")]` on a field changes this for that field.
+
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned, format_ident};
+use syn::spanned::Spanned;
+
+use crate::{get_best_effort_meta_items, get_deserialize_name_value, is_map_type};
+
+pub enum MergeStrategy {
+    /// Use `add_data` of the type.
+    Default,
+    /// Keep the first value, later values are read and dropped.
+    First,
+    /// Keep only the last value, also for a `Vec`.
+    Last,
+    /// Give every value to `add_data`, a `Vec` appends.
+    Append,
+    /// A second value is an error: `duplicate field`.
+    Error,
+    /// Append the text to a `String`.
+    Concat,
+    /// Call `fn(&mut Field, next_value)` for every value.
+    Function(syn::Path),
+}
+
+impl MergeStrategy {
+    // Parse `#[best_effort(merge = "first")]` and `#[best_effort(merge = "path::to::fn")]`
+    pub fn from_attrs(attrs: &[syn::Attribute]) -> Self {
+        let mut merge = MergeStrategy::Default;
+        for attr in attrs{
+            for meta_item in get_best_effort_meta_items(attr){
+                if let Some(lit) = get_deserialize_name_value(&meta_item, "merge") {
+                    merge = match lit.value().as_str() {
+                        "first" => MergeStrategy::First,
+                        "last" => MergeStrategy::Last,
+                        "append" => MergeStrategy::Append,
+                        "error" => MergeStrategy::Error,
+                        "concat" => MergeStrategy::Concat,
+                        // A path that can not be parsed is reported by `check.rs`.
+                        _ => match lit.parse() {
+                            Ok(path) => MergeStrategy::Function(path),
+                            Err(_) => MergeStrategy::Default,
+                        },
+                    };
+                }
+            }
+        }
+        merge
+    }
+
+    /// `first` and `error` have to know if the key was already seen, and so
+    /// does the default in strict mode.
+    /// The match arm sets `seen_fieldN` for these strategies.
+    pub fn tracks_seen(&self) -> bool {
+        matches!(self, MergeStrategy::Default | MergeStrategy::First | MergeStrategy::Error)
+    }
+
+    /// The body of the `Field::EnumN` match arm.
+    /// `field` is the struct field, `field_name` is the key of the field in the input, `place` is where the
+    /// value of the field is (`field` or `self.field`), `seen` the flag that is
+    /// set when the key is found.
+    /// `next_value` reads the value, `reset` is the default value of the field.
+    pub fn match_arm(
+        &self,
+        field: &syn::Field,
+        field_name: &str,
+        place: &TokenStream,
+        seen: &TokenStream,
+        next_value: &TokenStream,
+        reset: &TokenStream,
+    ) -> TokenStream {
+        let apply = self.apply(field, place);
+        let keeps_values = quote_spanned!{field.ty.span()=>
+            deserialize_best_effort::keeps_values(&#place, &__next_value)
+        };
+        match self {
+            // In strict mode a repeated key is an error if `add_data` would
+            // replace the value.
+            MergeStrategy::Default if !is_map_type(&field.ty) => quote!{
+                let __next_value = #next_value;
+                if #seen && deserialize_best_effort::is_strict()
+                    && !#keeps_values {
+                    return Err(serde::de::Error::duplicate_field(#field_name));
+                }
+                #seen = true;
+                #apply
+            },
+            // A map keeps the values of a repeated key.
+            MergeStrategy::Default | MergeStrategy::Append | MergeStrategy::Function(_) => quote!{
+                let __next_value = #next_value;
+                #apply
+            },
+            MergeStrategy::First => quote!{
+                if #seen {
+                    let _ = __map.next_value::<serde::de::IgnoredAny>();
+                } else {
+                    #seen = true;
+                    let __next_value = #next_value;
+                    #apply
+                }
+            },
+            MergeStrategy::Last => quote!{
+                let __next_value = #next_value;
+                #place = #reset;
+                #apply
+            },
+            MergeStrategy::Error => quote!{
+                if #seen {
+                    return Err(serde::de::Error::duplicate_field(#field_name));
+                }
+                #seen = true;
+                let __next_value = #next_value;
+                #apply
+            },
+            MergeStrategy::Concat => quote!{
+                let __next_value: String = #next_value;
+                #apply
+            },
+        }
+    }
+
+    /// Add `next_value` to the field, a map field adds every entry of the map
+    /// with its own key.
+    /// The trait is called with the span of the field type, so a type without
+    /// `DeserializeBestEffortTypes` is shown as an error on the field.
+    pub fn apply(&self, field: &syn::Field, place: &TokenStream) -> TokenStream {
+        let ident = field.ident.as_ref().unwrap();
+        match self {
+            MergeStrategy::Concat => quote!{
+                #place.push_str(&__next_value);
+            },
+            MergeStrategy::Function(path) => quote!{
+                #path(&mut #place, __next_value);
+            },
+            _ if is_map_type(&field.ty) => quote_spanned!{field.ty.span()=>
+                for (__key, __value) in __next_value {
+                    deserialize_best_effort::DeserializeBestEffortTypes::add_data(&mut #place, &__key, __value);
+                }
+            },
+            _ => quote_spanned!{field.ty.span()=>
+                deserialize_best_effort::DeserializeBestEffortTypes::add_data(&mut #place, stringify!(#ident), __next_value);
+            },
+        }
+    }
+}
+
+/// Set to `true` when the key of field `index` is found.
+pub fn get_seen_ident(index: usize) -> syn::Ident {
+    format_ident!("seen_field{}", index)
+}
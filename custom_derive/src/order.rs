@@ -0,0 +1,69 @@
+//! `#[best_effort(ordered)]`: remember the order of the keys.
+//!
+//! The values of a repeated key are added to the field of that key, so the
+//! order between the fields is lost. The `ordered` field (a
+//! `deserialize_best_effort::DocumentOrder`) gets every key that `visit_map`
+//! reads, with the number of times the key was found before. With this the
+//! original order of the values can be rebuilt.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::xml::{Collision, get_field_key, has_collision};
+use crate::{RenameRule, get_enum_ident, has_best_effort_flag, has_own_key, has_skipped_field};
+
+pub fn is_ordered(struct_field: &syn::Field) -> bool {
+    has_best_effort_flag(&struct_field.attrs, "ordered")
+}
+
+/// All fields marked with `#[best_effort(ordered)]`, `check.rs` makes sure
+/// there is only one.
+pub fn get_ordered_fields<'a>(struct_fields: &[&'a syn::Field]) -> Vec<&'a syn::Field> {
+    struct_fields.iter()
+        .filter(|field| is_ordered(field))
+        .copied()
+        .collect()
+}
+
+/// Add `__key` to the `ordered` field, before the value is read.
+/// Known fields use the name of the field (after `rename`), so an alias is
+/// added with the same name. An attribute field starts with `@`.
+pub fn record_key(struct_fields: &[&syn::Field], rename_all: RenameRule, catch_all: Option<&syn::Ident>) -> TokenStream {
+    let ordered = match get_ordered_fields(struct_fields).first() {
+        Some(field) => field.ident.as_ref().unwrap(),
+        None => return quote!{},
+    };
+    let mut key_match = quote!{};
+    for struct_field in struct_fields{
+        if !has_own_key(struct_field, catch_all) {
+            continue;
+        }
+        let field_ident = get_enum_ident(struct_field.ident.as_ref().unwrap());
+        let field_name = get_field_key(struct_field, rename_all);
+        key_match = quote!{
+            #key_match
+            Field::#field_ident => #field_name,
+        };
+    }
+    if has_skipped_field(struct_fields, catch_all) {
+        key_match = quote!{
+            #key_match
+            Field::__Skipped(__order_key) => __order_key,
+        };
+    }
+    if has_collision(struct_fields, catch_all, Collision::Error) {
+        key_match = quote!{
+            #key_match
+            Field::__Collision(__order_key, _) => __order_key,
+        };
+    }
+    quote!{
+        // Example of generated code here:
+        // let __order_key = match &__key { Field::id => "id", Field::__Unknown(key) => key.as_str() };
+        let __order_key: &str = match &__key {
+            #key_match
+            Field::__Unknown(__order_key) => __order_key.as_str(),
+        };
+        #ordered.push(__order_key);
+    }
+}
@@ -0,0 +1,328 @@
+//! `#[derive(SerializeBestEffort)]`: write a struct back like it was read.
+//!
+//! Every value of a field is written with the key of the field, so a `Vec` is
+//! written as a repeated key and `None` is not written. Flattened structs
+//! write their keys in the same map and the values of the catch-all field are
+//! written with their own key. All of them go through
+//! `SerializeBestEffortFlatten::serialize_entries`, a struct is written as a
+//! struct, so `serde-xml-rs` writes the root element. A struct with a
+//! catch-all or flattened field is written with `serialize_struct_map`, as a
+//! map that still gets the root element in xml.
+//! Enums, tuple and unit structs are one value, they use serde's own
+//! `Serialize` derive and only get `SerializeBestEffortTypes`.
+//!
+//! A struct with a `#[best_effort(ordered)]` field writes its values in the
+//! order of that field, so `field1` and `field2` are interleaved like in the
+//! input. Values that are not in the order are written after them.
+//! A `#[best_effort(attribute)]` field is written with its `@id` key, that
+//! `serde-xml-rs` can not write, there it is an error.
+
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned, format_ident};
+use syn::spanned::Spanned;
+
+use crate::generics::TypeName;
+use crate::order::{get_ordered_fields, is_ordered};
+use crate::xml::get_field_key;
+use crate::{
+    RenameRule, get_catch_all_field, get_deserialize_name_value, get_rename_all_attr, get_rename_attr,
+    get_serde_meta_items, get_struct_fields, has_serde_flag, is_catch_all, is_flatten_struct, is_map_type,
+};
+
+pub fn impl_serialize_best_effort(ast: &syn::DeriveInput) -> TokenStream {
+    let name = TypeName::new(ast);
+    let impl_types = name.impl_ser_trait(quote!{ deserialize_best_effort::SerializeBestEffortTypes });
+    let one_value = quote!{
+        #impl_types {
+            type Value = Self;
+
+            fn values(&self) -> Box<dyn Iterator<Item = &Self> + '_> {
+                Box::new(std::iter::once(self))
+            }
+        }
+    };
+    match &ast.data {
+        syn::Data::Struct(syn::DataStruct{ fields: syn::Fields::Named(_), .. }) => (),
+        _ => return one_value,
+    }
+
+    let fields = get_struct_fields(ast);
+    let rename_all = get_rename_all_attr(&ast.attrs);
+    let catch_all = get_catch_all_field(&fields);
+    let struct_name = get_rename_attr(&ast.attrs).unwrap_or_else(|| name.ident.to_string());
+
+    let ordered = get_ordered_fields(&fields).first().map(|field| field.ident.as_ref().unwrap());
+
+    let map_write = quote!{
+        deserialize_best_effort::SerializeEntries::serialize_field(__map, __key, &__value)?;
+    };
+    let map_entries = match ordered {
+        Some(ordered) => quote!{
+            let mut __write_entry_at = deserialize_best_effort::SerializeBestEffortFlatten::write_entry_at::<M>(self);
+            for __entry in &self.#ordered {
+                __write_entry_at(&__entry.key, __entry.index, __map)?;
+            }
+            deserialize_best_effort::SerializeBestEffortFlatten::serialize_remaining_entries(self, &self.#ordered, __map)
+        },
+        None => {
+            let entries = set_serialize_fields(&fields, rename_all, catch_all, None, &map_write);
+            quote!{
+                #entries
+                Ok(())
+            }
+        },
+    };
+    let write_entry_at = set_write_entry_at(&fields, rename_all, catch_all, &quote!{
+        #map_write
+        return Ok(true);
+    });
+    let remaining_entries = set_serialize_fields(&fields, rename_all, catch_all, Some(&quote!{ __order }), &map_write);
+
+    // A struct with a catch-all or flattened field has keys that are only
+    // known at runtime, it is written as a map (with a root element in xml).
+    let has_runtime_keys = fields.iter()
+        .any(|field| is_serialized(field) && (is_catch_all(field.ident.as_ref().unwrap(), catch_all) || is_flatten_struct(field, catch_all)));
+    let len = fields.iter().filter(|field| is_serialized(field)).count();
+    let body = if has_runtime_keys {
+        quote!{
+            deserialize_best_effort::serialize_struct_map(serializer, #struct_name, self)
+        }
+    } else {
+        quote!{
+            let mut __struct = deserialize_best_effort::StructEntries(
+                serde::ser::Serializer::serialize_struct(serializer, #struct_name, #len)?
+            );
+            deserialize_best_effort::SerializeBestEffortFlatten::serialize_entries(self, &mut __struct)?;
+            __struct.end()
+        }
+    };
+
+    let impl_serialize = name.impl_ser_trait(quote!{ serde::ser::Serialize });
+    let impl_flatten = name.impl_ser_trait(quote!{ deserialize_best_effort::SerializeBestEffortFlatten });
+    quote!{
+        #one_value
+
+        #impl_serialize {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::ser::Serializer,
+            {
+                #body
+            }
+        }
+
+        #impl_flatten {
+            fn serialize_entries<M>(&self, __map: &mut M) -> Result<(), M::Error>
+            where
+                M: deserialize_best_effort::SerializeEntries,
+            {
+                #map_entries
+            }
+
+            fn write_entry_at<'__a, M>(&'__a self) -> deserialize_best_effort::WriteEntryAt<'__a, M>
+            where
+                M: deserialize_best_effort::SerializeEntries + '__a,
+            {
+                #write_entry_at
+            }
+
+            fn serialize_remaining_entries<M>(&self, __order: &deserialize_best_effort::DocumentOrder, __map: &mut M) -> Result<(), M::Error>
+            where
+                M: deserialize_best_effort::SerializeEntries,
+            {
+                #remaining_entries
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Skipped fields and the `ordered` field are not written.
+fn is_serialized(struct_field: &syn::Field) -> bool {
+    !has_serde_flag(&struct_field.attrs, "skip") && !has_serde_flag(&struct_field.attrs, "skip_serializing")
+        && !is_ordered(struct_field)
+}
+
+/// Write all values of the fields, `write` writes `__value` with `__key`.
+/// With an `order` only the values that are not in that `DocumentOrder` are
+/// written.
+fn set_serialize_fields(
+    struct_fields: &[&syn::Field],
+    rename_all: RenameRule,
+    catch_all: Option<&syn::Ident>,
+    order: Option<&TokenStream>,
+    write: &TokenStream,
+) -> TokenStream {
+    let mut parse_gen = quote!{};
+    for struct_field in struct_fields{
+        if !is_serialized(struct_field) {
+            continue;
+        }
+        let field = struct_field.ident.as_ref().unwrap();
+        let write_field = if is_catch_all(field, catch_all) {
+            match order {
+                Some(order) => quote!{
+                    deserialize_best_effort::SerializeBestEffortCatchAll::serialize_remaining_entries(&self.#field, #order, __map)?;
+                },
+                None => quote!{
+                    for (__key, __value) in deserialize_best_effort::SerializeBestEffortCatchAll::entries(&self.#field) {
+                        deserialize_best_effort::serialize_unknown_entry(__map, __key, __value)?;
+                    }
+                },
+            }
+        } else if is_flatten_struct(struct_field, catch_all) {
+            match order {
+                Some(order) => quote!{
+                    deserialize_best_effort::SerializeBestEffortFlatten::serialize_remaining_entries(&self.#field, #order, __map)?;
+                },
+                None => quote!{
+                    deserialize_best_effort::SerializeBestEffortFlatten::serialize_entries(&self.#field, __map)?;
+                },
+            }
+        } else {
+            let field_name = get_field_key(struct_field, rename_all);
+            let values = get_values(struct_field);
+            let skip = match order {
+                Some(order) => quote!{ .skip(#order.count(#field_name)) },
+                None => quote!{},
+            };
+            quote!{
+                // Example of generated code here:
+                // for __value in SerializeBestEffortTypes::values(&self.id) { map.serialize_entry("id", __value)?; }
+                for __value in #values #skip {
+                    let __key = #field_name;
+                    #write
+                }
+            }
+        };
+        let write_field = skip_serializing_if(struct_field, write_field);
+        parse_gen = quote!{
+            #parse_gen
+            #write_field
+        };
+    }
+    parse_gen
+}
+
+/// The values of a field with its own key, a map is written with
+/// `MapValues`. The trait is called with the span of the field type, so a
+/// type without `SerializeBestEffortTypes` is shown as an error on the field.
+fn get_values(struct_field: &syn::Field) -> TokenStream {
+    let field = struct_field.ident.as_ref().unwrap();
+    let map_values = if is_map_type(&struct_field.ty) {
+        quote!{ .map(deserialize_best_effort::MapValues) }
+    } else {
+        quote!{}
+    };
+    quote_spanned!{struct_field.ty.span()=>
+        deserialize_best_effort::SerializeBestEffortTypes::values(&self.#field)#map_values
+    }
+}
+
+/// The body of `write_entry_at`: a cursor for every field and a closure that
+/// writes the value number `__entry_index` of the key `__entry_key`, `write`
+/// writes `__value` with `__key` and returns.
+/// A key that is not a field of this struct is passed to the flattened
+/// structs and then to the catch-all field, like in `visit_map`.
+fn set_write_entry_at(
+    struct_fields: &[&syn::Field],
+    rename_all: RenameRule,
+    catch_all: Option<&syn::Ident>,
+    write: &TokenStream,
+) -> TokenStream {
+    let mut cursors = quote!{};
+    let mut own_keys = quote!{};
+    let mut other_keys = quote!{};
+    for (i, struct_field) in struct_fields.iter().enumerate(){
+        if !is_serialized(struct_field) {
+            continue;
+        }
+        let field = struct_field.ident.as_ref().unwrap();
+        let cursor = format_ident!("__cursor{}", i);
+        let write_field = if is_catch_all(field, catch_all) {
+            cursors = quote!{
+                #cursors
+                let mut #cursor = deserialize_best_effort::EntriesCursor::new(&self.#field);
+            };
+            quote!{
+                if #cursor.write_entry_at(__entry_key, __entry_index, __map)? {
+                    return Ok(true);
+                }
+            }
+        } else if is_flatten_struct(struct_field, catch_all) {
+            cursors = quote!{
+                #cursors
+                let mut #cursor = deserialize_best_effort::SerializeBestEffortFlatten::write_entry_at::<M>(&self.#field);
+            };
+            quote!{
+                if #cursor(__entry_key, __entry_index, __map)? {
+                    return Ok(true);
+                }
+            }
+        } else {
+            let field_name = get_field_key(struct_field, rename_all);
+            let values = get_values(struct_field);
+            cursors = quote!{
+                #cursors
+                let mut #cursor = deserialize_best_effort::ValuesCursor::new(#values);
+            };
+            let write_field = skip_serializing_if(struct_field, quote!{
+                if let Some(__value) = #cursor.value_at(__entry_index) {
+                    let __key = #field_name;
+                    #write
+                }
+            });
+            own_keys = quote!{
+                #own_keys
+                #field_name => {
+                    #write_field
+                },
+            };
+            continue;
+        };
+        let write_field = skip_serializing_if(struct_field, write_field);
+        other_keys = quote!{
+            #other_keys
+            #write_field
+        };
+    }
+    quote!{
+        #cursors
+        Box::new(move |__entry_key: &str, __entry_index: usize, __map: &mut M| {
+            match __entry_key {
+                #own_keys
+                _ => {
+                    #other_keys
+                },
+            }
+            Ok(false)
+        })
+    }
+}
+
+/// Only run `write_field` if `#[serde(skip_serializing_if = "...")]` is false.
+fn skip_serializing_if(struct_field: &syn::Field, write_field: TokenStream) -> TokenStream {
+    let field = struct_field.ident.as_ref().unwrap();
+    match get_skip_serializing_if_attr(&struct_field.attrs) {
+        Some(skip_if) => quote!{
+            if !#skip_if(&self.#field) {
+                #write_field
+            }
+        },
+        None => write_field,
+    }
+}
+
+// Parse `#[serde(skip_serializing_if = "path")]`
+fn get_skip_serializing_if_attr(attrs: &[syn::Attribute]) -> Option<syn::Path> {
+    let mut skip_if = None;
+    for attr in attrs{
+        for meta_item in get_serde_meta_items(attr){
+            if let Some(lit) = get_deserialize_name_value(&meta_item, "skip_serializing_if") {
+                // A path that can not be parsed is reported by `check.rs`.
+                skip_if = lit.parse().ok();
+            }
+        }
+    }
+    skip_if
+}
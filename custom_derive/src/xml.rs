@@ -0,0 +1,213 @@
+//! `#[best_effort(attribute)]` and `#[best_effort(element)]`: XML attributes
+//! and child elements with the same name.
+//!
+//! `serde-xml-rs` gives the attributes and the child elements of an element
+//! as keys of the same map, so `<item id="1"><id>2</id></item>` has the key
+//! `id` two times. A key that starts with `@` is an attribute, with
+//! `deserialize_best_effort::from_reader_with_attributes` the attributes of
+//! `serde-xml-rs` get the `@`. A field marked with `attribute` only gets
+//! attributes, a field marked with `element` only gets elements, other fields
+//! get both. An attribute that is not a field goes to the catch-all field as
+//! `@name`.
+//!
+//! A key of the wrong kind for the marked field of that name (an element
+//! `id` when there is only an attribute field `id`) is a collision. What
+//! happens then is set with `#[best_effort(collision = "...")]`:
+//! `"unknown"` (the default) handles it like an unknown key, `"ignore"` reads
+//! and drops it and `"error"` fails.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::{RenameRule, get_alias_attrs, get_best_effort_meta_items, get_deserialize_name_value, get_field_name, has_best_effort_flag, has_own_key};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Attribute,
+    Element,
+}
+
+impl Kind {
+    pub fn from_attrs(attrs: &[syn::Attribute]) -> Option<Kind> {
+        if has_best_effort_flag(attrs, "attribute") {
+            Some(Kind::Attribute)
+        } else if has_best_effort_flag(attrs, "element") {
+            Some(Kind::Element)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Attribute => "attribute",
+            Kind::Element => "element",
+        }
+    }
+
+    fn other(self) -> Kind {
+        match self {
+            Kind::Attribute => Kind::Element,
+            Kind::Element => Kind::Attribute,
+        }
+    }
+
+    /// The key of a value of this kind in `DocumentOrder` and the catch-all
+    /// field. Ex: `"@id"` for an attribute.
+    fn key(self, name: &str) -> String {
+        match self {
+            Kind::Attribute => format!("@{}", name),
+            Kind::Element => name.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Collision {
+    Unknown,
+    Ignore,
+    Error,
+}
+
+impl Collision {
+    pub const NAMES: &'static [&'static str] = &["unknown", "ignore", "error"];
+
+    /// Parse `#[best_effort(collision = "...")]`
+    pub fn from_attrs(attrs: &[syn::Attribute]) -> Collision {
+        let mut collision = Collision::Unknown;
+        for attr in attrs{
+            for meta_item in get_best_effort_meta_items(attr){
+                if let Some(lit) = get_deserialize_name_value(&meta_item, "collision") {
+                    // A value that does not exist is reported by `check.rs`.
+                    collision = match lit.value().as_str() {
+                        "ignore" => Collision::Ignore,
+                        "error" => Collision::Error,
+                        _ => Collision::Unknown,
+                    };
+                }
+            }
+        }
+        collision
+    }
+}
+
+/// Does the struct have a field marked with `attribute` or `element`, only
+/// then the kind of a key is used.
+pub fn has_kind_fields(struct_fields: &[&syn::Field], catch_all: Option<&syn::Ident>) -> bool {
+    struct_fields.iter()
+        .any(|field| has_own_key(field, catch_all) && Kind::from_attrs(&field.attrs).is_some())
+}
+
+/// Does a marked field use the `collision` policy, then `Field` needs the
+/// variant for it.
+pub fn has_collision(struct_fields: &[&syn::Field], catch_all: Option<&syn::Ident>, collision: Collision) -> bool {
+    struct_fields.iter().any(|field| {
+        has_own_key(field, catch_all) && Kind::from_attrs(&field.attrs).is_some()
+            && Collision::from_attrs(&field.attrs) == collision
+    })
+}
+
+/// The key of the field in `DocumentOrder` and when it is serialized, an
+/// attribute starts with `@` (attributes are not serialized).
+pub fn get_field_key(struct_field: &syn::Field, rename_all: RenameRule) -> String {
+    let field_name = get_field_name(struct_field, rename_all);
+    match Kind::from_attrs(&struct_field.attrs) {
+        Some(kind) => kind.key(&field_name),
+        None => field_name,
+    }
+}
+
+/// The body of `Field::from_key` for a struct with marked fields. `arms` are
+/// the match arms of each field (`"id" => Field::id,`).
+/// The arms of marked fields only match their kind and come first, so an
+/// unmarked field with the same name gets the other kind.
+pub fn get_field_enum_match(
+    struct_fields: &[&syn::Field],
+    rename_all: RenameRule,
+    catch_all: Option<&syn::Ident>,
+    arms: &[(&syn::Field, TokenStream)],
+) -> TokenStream {
+    let mut marked_arms = quote!{};
+    let mut other_arms = quote!{};
+    for (struct_field, field_enum_ident) in arms{
+        let names = struct_field_names(struct_field, rename_all);
+        let kind = match Kind::from_attrs(&struct_field.attrs) {
+            Some(kind) => kind,
+            None => {
+                other_arms = quote!{
+                    #other_arms
+                    #(#names => Field::#field_enum_ident,)*
+                };
+                continue;
+            },
+        };
+        let guard = match kind {
+            Kind::Attribute => quote!{ if __attribute },
+            Kind::Element => quote!{ if !__attribute },
+        };
+        marked_arms = quote!{
+            #marked_arms
+            // "id" if __attribute => Field::id,
+            #(#names #guard => Field::#field_enum_ident,)*
+        };
+        if has_other_field(struct_fields, struct_field, rename_all, catch_all) {
+            continue;
+        }
+        // Without an arm the key of the wrong kind is `Field::__Unknown`.
+        if let Some(collision) = collision_arm(struct_field, kind, &get_field_name(struct_field, rename_all)) {
+            marked_arms = quote!{
+                #marked_arms
+                #(#names => #collision,)*
+            };
+        }
+    }
+    quote!{
+        let (value, __attribute) = match value.strip_prefix('@') {
+            Some(value) => (value, true),
+            None => (value, false),
+        };
+        match value {
+            #marked_arms
+            #other_arms
+            _ if __attribute => Field::__Unknown(format!("@{}", value)),
+            _ => Field::__Unknown(value.to_string()),
+        }
+    }
+}
+
+/// The name and the aliases of a field.
+fn struct_field_names(struct_field: &syn::Field, rename_all: RenameRule) -> Vec<String> {
+    let mut names = vec![get_field_name(struct_field, rename_all)];
+    names.extend(get_alias_attrs(&struct_field.attrs));
+    names
+}
+
+/// Is there an other field with the same name that gets the other kind, then
+/// there is no collision.
+fn has_other_field(
+    struct_fields: &[&syn::Field],
+    struct_field: &syn::Field,
+    rename_all: RenameRule,
+    catch_all: Option<&syn::Ident>,
+) -> bool {
+    let field_name = get_field_name(struct_field, rename_all);
+    struct_fields.iter().any(|other| {
+        other.ident != struct_field.ident && has_own_key(other, catch_all)
+            && Kind::from_attrs(&other.attrs) != Kind::from_attrs(&struct_field.attrs)
+            && get_field_name(other, rename_all) == field_name
+    })
+}
+
+/// The `Field` of a key with the wrong kind for the field `kind`.
+fn collision_arm(struct_field: &syn::Field, kind: Kind, field_name: &str) -> Option<TokenStream> {
+    let found = kind.other();
+    let key = found.key(field_name);
+    match Collision::from_attrs(&struct_field.attrs) {
+        Collision::Unknown => None,
+        Collision::Ignore => Some(quote!{ Field::__Skipped(#key) }),
+        Collision::Error => {
+            let message = format!("found {} `{}`, expected {} `{}`", found.name(), field_name, kind.name(), field_name);
+            Some(quote!{ Field::__Collision(#key, #message) })
+        },
+    }
+}
@@ -1,61 +1,350 @@
 
 use serde::{Deserialize};
-use std::collections::HashMap;
-use serde_json::Value;
+use serde::de::{self, MapAccess};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::rc::Rc;
+use std::sync::Arc;
+use serde_json::{Map, Value};
+
+mod coerce;
+mod map;
+mod order;
+mod report;
+mod scope;
+mod serialize;
+mod strict;
+mod unknown;
+mod xml;
+#[cfg(test)]
+mod tests;
+pub use map::MapEntries;
+pub use order::{DocumentOrder, OrderEntry};
+pub use unknown::{UnknownField, UnknownFields};
+pub use xml::{WithAttributes, from_reader_with_attributes};
+pub use serialize::{
+    SerializeBestEffortTypes, SerializeBestEffortCatchAll, SerializeBestEffortFlatten, SerializeEntries, StructEntries,
+    MapValues, ValuesCursor, EntriesCursor, WriteEntryAt, serialize_unknown_entry, serialize_struct_map,
+};
+pub use strict::{Strict, with_strict, from_reader_strict, is_strict, strict_result, try_strict, keeps_values, skip_remaining_elements};
+pub use report::{
+    BestEffortIssue, BestEffortReport, with_report, from_reader_with_report,
+    next_value_or_default, next_value_or_report, next_value_required, next_value_with,
+    next_element_or_default, next_element_or_report, next_element_with, deserialize_or_default, unwrap_or_report,
+};
 
 pub trait DeserializeBestEffort<'de>: Deserialize<'de>{}
 
+/// `serde-xml-rs` gives the text of an element as `{"$value": "text"}`.
+/// This replaces those objects with the text itself, so the value can be
+/// deserialized into the actual types (used by tagged and untagged enums).
+pub fn unwrap_text_nodes(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            if map.len() == 1 && map.contains_key("$value") {
+                unwrap_text_nodes(map.into_iter().next().unwrap().1)
+            } else {
+                Value::Object(map.into_iter()
+                    .map(|(key, value)| (key, unwrap_text_nodes(value)))
+                    .collect())
+            }
+        },
+        Value::Array(list) => Value::Array(list.into_iter().map(unwrap_text_nodes).collect()),
+        other => other,
+    }
+}
+
+/// A struct that can be used with `#[serde(flatten)]` in a struct that
+/// derives `DeserializeBestEffort`. This is implemented by the derive for
+/// every struct with named fields.
+/// The keys of the struct are in the map of the outer struct, so the outer
+/// struct gives every key it does not know to `flatten_next_value`.
+pub trait DeserializeBestEffortFlatten<'de>: Sized {
+    /// Which keys were found, for `required` fields and merge strategies.
+    type Seen;
+
+    /// The value before any key is found.
+    fn flatten_default() -> Self;
+
+    fn flatten_seen() -> Self::Seen;
+
+    /// Read the value of `key` if it is a field of this struct (or of a
+    /// struct flattened into it). Returns `false` if the key is not used,
+    /// then the value is not read.
+    /// `position` is the position of the key in the map, for `UnknownFields`.
+    fn flatten_next_value<A>(&mut self, seen: &mut Self::Seen, key: &str, position: usize, map: &mut A) -> Result<bool, A::Error>
+    where
+        A: MapAccess<'de>;
+
+    /// Called after the last key, returns `missing_field` for `required` fields.
+    fn flatten_end<E>(&self, seen: &Self::Seen) -> Result<(), E>
+    where
+        E: de::Error;
+}
+
 pub trait DeserializeBestEffortTypes<'de, T> where
     T: DeserializeBestEffortTypes<'de,T>{
     fn add_data(&mut self, key: &str, next_value: T);
-}
 
-impl<'de> DeserializeBestEffortTypes<'de,i32> for i32{
-    fn add_data(&mut self, _key: &str, next_value: i32){
-        *self = next_value;
+    /// Like `add_data`, with the position of the key in the map (or the
+    /// index in the sequence). Used for the catch-all field, only
+    /// `UnknownFields` keeps the position.
+    fn add_data_at(&mut self, key: &str, next_value: T, _position: usize) {
+        self.add_data(key, next_value);
     }
+
+    /// `true` if `add_data` keeps the values that are already there, like a
+    /// list or a map. In strict mode a repeated key is an error for the
+    /// other types.
+    fn keeps_values(&self) -> bool {
+        false
+    }
+}
+
+/// Types where a new value replaces the old value.
+macro_rules! impl_replace_value {
+    ($($type_:ty),* $(,)?) => {
+        $(
+            impl<'de> DeserializeBestEffortTypes<'de,$type_> for $type_{
+                fn add_data(&mut self, _key: &str, next_value: $type_){
+                    *self = next_value;
+                }
+            }
+        )*
+    };
 }
 
-impl<'de> DeserializeBestEffortTypes<'de,u32> for u32{
-    fn add_data(&mut self, _key: &str, next_value: u32){
+impl_replace_value!(
+    i8, i16, i32, i64, i128, isize,
+    u8, u16, u32, u64, u128, usize,
+    f32, f64,
+    bool, char,
+    String, Box<str>,
+    Value,
+);
+
+impl<'de: 'a, 'a> DeserializeBestEffortTypes<'de,&'a str> for &'a str {
+    fn add_data(&mut self, _key: &str, next_value: &'a str){
         *self = next_value;
     }
 }
 
-impl<'de> DeserializeBestEffortTypes<'de,String> for String {
-    fn add_data(&mut self, _key: &str, next_value: String){
-        *self = next_value;
+/// Keyed collections add the value under its key. A key that is found again
+/// is merged with `add_data` of the value, so `HashMap<String, Vec<T>>`
+/// collects all values of a repeated key.
+/// As the catch-all field the key is the unknown key of the struct, as a
+/// field with its own key every entry of the map is added (see `map.rs`).
+impl<'de,T,V> DeserializeBestEffortTypes<'de,T> for HashMap<String, V> where
+    T: DeserializeBestEffortTypes<'de,T>,
+    V: DeserializeBestEffortTypes<'de,T> + Default {
+    fn add_data(&mut self, key: &str, next_value: T){
+        self.entry(key.to_string()).or_default().add_data(key, next_value);
+    }
+
+    fn keeps_values(&self) -> bool {
+        true
     }
 }
 
-impl<'de> DeserializeBestEffortTypes<'de,Value> for Value {
-    fn add_data(&mut self, _key: &str, next_value: Value){
-        *self = next_value;
+impl<'de,T,V> DeserializeBestEffortTypes<'de,T> for BTreeMap<String, V> where
+    T: DeserializeBestEffortTypes<'de,T>,
+    V: DeserializeBestEffortTypes<'de,T> + Default {
+    fn add_data(&mut self, key: &str, next_value: T){
+        self.entry(key.to_string()).or_default().add_data(key, next_value);
+    }
+
+    fn keeps_values(&self) -> bool {
+        true
     }
 }
 
-impl<'de> DeserializeBestEffortTypes<'de, Value> for HashMap<String, Value> {
+impl<'de> DeserializeBestEffortTypes<'de, Value> for Map<String, Value> {
     fn add_data(&mut self, key: &str, next_value: Value){
         self.insert(key.to_string(), next_value);
     }
+
+    fn keeps_values(&self) -> bool {
+        true
+    }
 }
 
 impl<'de,T> DeserializeBestEffortTypes<'de,T> for T where
-    T: DeserializeBestEffort<'de> + Default {
+    T: DeserializeBestEffort<'de> {
     fn add_data(&mut self, _key: &str, next_value: T){
         *self = next_value;
     }
 }
 
 impl<'de,T> DeserializeBestEffortTypes<'de,T> for Vec<T> where
-    T: DeserializeBestEffortTypes<'de,T> + Default {
+    T: DeserializeBestEffortTypes<'de,T> {
     fn add_data(&mut self, _key: &str, next_value: T){
         self.push(next_value);
     }
+
+    fn keeps_values(&self) -> bool {
+        true
+    }
+}
+
+impl<'de,T> DeserializeBestEffortTypes<'de,T> for VecDeque<T> where
+    T: DeserializeBestEffortTypes<'de,T> {
+    fn add_data(&mut self, _key: &str, next_value: T){
+        self.push_back(next_value);
+    }
+
+    fn keeps_values(&self) -> bool {
+        true
+    }
+}
+
+/// Sets drop values that are already in the set.
+impl<'de,T> DeserializeBestEffortTypes<'de,T> for BTreeSet<T> where
+    T: DeserializeBestEffortTypes<'de,T> + Ord {
+    fn add_data(&mut self, _key: &str, next_value: T){
+        self.insert(next_value);
+    }
+
+    fn keeps_values(&self) -> bool {
+        true
+    }
+}
+
+impl<'de,T> DeserializeBestEffortTypes<'de,T> for HashSet<T> where
+    T: DeserializeBestEffortTypes<'de,T> + Eq + Hash {
+    fn add_data(&mut self, _key: &str, next_value: T){
+        self.insert(next_value);
+    }
+
+    fn keeps_values(&self) -> bool {
+        true
+    }
+}
+
+/// Arrays are filled from the start: the value goes into the first element
+/// that is still `None`. A value past the end of the array is dropped and
+/// recorded in the report (an error in strict mode).
+impl<'de,T,const N: usize> DeserializeBestEffortTypes<'de,T> for [Option<T>; N] where
+    T: DeserializeBestEffortTypes<'de,T> {
+    fn add_data(&mut self, key: &str, next_value: T){
+        match self.iter_mut().find(|element| element.is_none()) {
+            Some(element) => *element = Some(next_value),
+            None => report::record_dropped::<Self>(key, &format_args!("invalid length {}, expected an array of length {}", N + 1, N)),
+        }
+    }
+
+    fn keeps_values(&self) -> bool {
+        true
+    }
+}
+
+/// Wrappers give the inner value to `add_data` of the inner type.
+/// So `Box<Node>` can be used for recursive types and `Vec<Box<Node>>` for a
+/// list of them.
+// `Box` takes the inner value itself: `impl for Box<T>` with `Box<T>` as value
+// would overlap with `impl for T where T: DeserializeBestEffort`, because
+// `Box` is `#[fundamental]`. That is why `Vec` and `Option` of a `Box` need
+// their own impl.
+impl<'de,T> DeserializeBestEffortTypes<'de,T> for Box<T> where
+    T: DeserializeBestEffortTypes<'de,T> {
+    fn add_data(&mut self, key: &str, next_value: T){
+        (**self).add_data(key, next_value);
+    }
+
+    fn keeps_values(&self) -> bool {
+        (**self).keeps_values()
+    }
+}
+
+impl<'de,T> DeserializeBestEffortTypes<'de,T> for Vec<Box<T>> where
+    T: DeserializeBestEffortTypes<'de,T> {
+    fn add_data(&mut self, _key: &str, next_value: T){
+        self.push(Box::new(next_value));
+    }
+
+    fn keeps_values(&self) -> bool {
+        true
+    }
+}
+
+impl<'de,T> DeserializeBestEffortTypes<'de,T> for Option<Box<T>> where
+    T: DeserializeBestEffortTypes<'de,T> {
+    fn add_data(&mut self, _key: &str, next_value: T){
+        *self = Some(Box::new(next_value));
+    }
+}
+
+// `Rc` and `Arc` are not shared while they are deserialized, if they are the
+// value is replaced instead.
+impl<'de,T,V> DeserializeBestEffortTypes<'de,V> for Rc<T> where
+    V: DeserializeBestEffortTypes<'de,V>,
+    T: DeserializeBestEffortTypes<'de,V> + Default {
+    fn add_data(&mut self, key: &str, next_value: V){
+        match Rc::get_mut(self) {
+            Some(inner) => inner.add_data(key, next_value),
+            None => {
+                let mut inner = T::default();
+                inner.add_data(key, next_value);
+                *self = Rc::new(inner);
+            },
+        }
+    }
+
+    fn keeps_values(&self) -> bool {
+        (**self).keeps_values()
+    }
+}
+
+impl<'de,T,V> DeserializeBestEffortTypes<'de,V> for Arc<T> where
+    V: DeserializeBestEffortTypes<'de,V>,
+    T: DeserializeBestEffortTypes<'de,V> + Default {
+    fn add_data(&mut self, key: &str, next_value: V){
+        match Arc::get_mut(self) {
+            Some(inner) => inner.add_data(key, next_value),
+            None => {
+                let mut inner = T::default();
+                inner.add_data(key, next_value);
+                *self = Arc::new(inner);
+            },
+        }
+    }
+
+    fn keeps_values(&self) -> bool {
+        (**self).keeps_values()
+    }
+}
+
+impl<'de,'a,B> DeserializeBestEffortTypes<'de,Cow<'a,B>> for Cow<'a,B> where
+    B: ToOwned + ?Sized,
+    B::Owned: DeserializeBestEffortTypes<'de,B::Owned> {
+    fn add_data(&mut self, key: &str, next_value: Cow<'a,B>){
+        self.to_mut().add_data(key, next_value.into_owned());
+    }
+}
+
+impl<'de,T,V> DeserializeBestEffortTypes<'de,V> for RefCell<T> where
+    V: DeserializeBestEffortTypes<'de,V>,
+    T: DeserializeBestEffortTypes<'de,V> {
+    fn add_data(&mut self, key: &str, next_value: V){
+        self.get_mut().add_data(key, next_value);
+    }
+
+    fn keeps_values(&self) -> bool {
+        self.borrow().keeps_values()
+    }
+}
+
+impl<'de,T,V> DeserializeBestEffortTypes<'de,V> for Cell<T> where
+    V: DeserializeBestEffortTypes<'de,V>,
+    T: DeserializeBestEffortTypes<'de,V> {
+    fn add_data(&mut self, key: &str, next_value: V){
+        self.get_mut().add_data(key, next_value);
+    }
 }
 
 impl<'de,T> DeserializeBestEffortTypes<'de,T> for Option<T> where
-    T: DeserializeBestEffortTypes<'de,T> + Default {
+    T: DeserializeBestEffortTypes<'de,T> {
     fn add_data(&mut self, _key: &str, next_value: T){
         *self = Some(next_value);
     }
@@ -68,7 +357,7 @@ impl<'de> DeserializeBestEffortTypes<'de,String> for Option<()> {
 }
 
 impl<'de,T> DeserializeBestEffortTypes<'de,T> for Option<Vec<T>> where
-    T: DeserializeBestEffortTypes<'de,T> + Default {
+    T: DeserializeBestEffortTypes<'de,T> {
     fn add_data(&mut self, _key: &str, next_value: T){
         if self.is_none() {
             *self = Some(Vec::new());
@@ -77,4 +366,8 @@ impl<'de,T> DeserializeBestEffortTypes<'de,T> for Option<Vec<T>> where
             list.push(next_value);
         }
     }
+
+    fn keeps_values(&self) -> bool {
+        true
+    }
 }
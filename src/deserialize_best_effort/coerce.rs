@@ -0,0 +1,407 @@
+// Lenient parsing of numbers and booleans.
+//
+// XML gives every scalar as text, and not all text is written the way
+// `FromStr` wants it: ` 12 `, `12.0`, `1,234`, `0x1F`, `Y` or `yes`.
+// When a number or a bool is asked for from a human readable format, the
+// value is read with `deserialize_any` and text is parsed here. Formats that
+// are not self-describing (bincode) are not coerced. Every value of an other
+// type (a string for a number, a number for a bool, a float for an integer)
+// and every text that did not parse exactly is recorded in the report (see
+// `with_report`). `serde-xml-rs` gives every scalar as text, so there text
+// that parses exactly is not recorded.
+use std::any::type_name;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Unexpected, Visitor};
+
+use super::report::record_coercion;
+
+/// The kind of value that was asked for.
+#[derive(Clone, Copy)]
+pub(super) enum Coerce {
+    Int,
+    Float,
+    Bool,
+}
+
+/// Parse an integer: trims whitespace, accepts `+`, thousands separators
+/// (`1,234` or `1_234`), hex (`0x1F`) and floats without a fraction (`12.0`).
+fn parse_int(text: &str) -> Option<i128> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+        None => {
+            let digits = remove_thousands_separators(digits)?;
+            match digits.parse::<i128>() {
+                Ok(value) => value,
+                Err(_) => {
+                    let value: f64 = digits.parse().ok()?;
+                    if !value.is_finite() || value.fract() != 0.0 {
+                        return None;
+                    }
+                    value as i128
+                },
+            }
+        },
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Parse a float: trims whitespace and accepts thousands separators.
+fn parse_float(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value: f64 = remove_thousands_separators(digits)?.parse().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// Parse a bool: `true`, `yes`, `y`, `on`, `t`, `1` and their opposites,
+/// in any case.
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_lowercase().as_str() {
+        "true" | "yes" | "y" | "on" | "t" | "1" => Some(true),
+        "false" | "no" | "n" | "off" | "f" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// `1,234,567.5` -> `1234567.5`. Only groups of 3 digits are accepted,
+/// so `1,5` is not read as `15`.
+fn remove_thousands_separators(text: &str) -> Option<String> {
+    let separator = match (text.contains(','), text.contains('_')) {
+        (false, false) => return Some(text.to_string()),
+        (true, false) => ',',
+        (false, true) => '_',
+        (true, true) => return None,
+    };
+    let (integer, fraction) = match text.find('.') {
+        Some(index) => text.split_at(index),
+        None => (text, ""),
+    };
+    if fraction.contains(separator) {
+        return None;
+    }
+    let mut groups = integer.split(separator);
+    let first = groups.next()?;
+    let first_ok = !first.is_empty() && first.len() <= 3 && first.bytes().all(|b| b.is_ascii_digit());
+    if !first_ok || !groups.all(|group| group.len() == 3 && group.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+    Some(format!("{}{}", integer.replace(separator, ""), fraction))
+}
+
+/// `true` if every scalar of the deserializer `D` is text. There is no method
+/// for this on `Deserializer`, so `serde-xml-rs` is recognized by its type
+/// name (also when it is wrapped, like in `WithAttributes`).
+pub(super) fn is_text_only<D>() -> bool {
+    type_name::<D>().contains("serde_xml_rs::")
+}
+
+/// Wraps the visitor of a number or bool, and coerces values of other types.
+/// `text_only` is `is_text_only` of the deserializer.
+pub(super) struct CoerceVisitor<V> {
+    visitor: V,
+    coerce: Coerce,
+    text_only: bool,
+}
+
+impl<V> CoerceVisitor<V> {
+    pub(super) fn new(visitor: V, coerce: Coerce, text_only: bool) -> Self {
+        CoerceVisitor{ visitor, coerce, text_only }
+    }
+}
+
+impl<'de, V> CoerceVisitor<V>
+where
+    V: Visitor<'de>,
+{
+    fn visit_int<E>(self, value: i128) -> Result<V::Value, E>
+    where
+        E: de::Error,
+    {
+        if let Ok(value) = u64::try_from(value) {
+            self.visitor.visit_u64(value)
+        } else if let Ok(value) = i64::try_from(value) {
+            self.visitor.visit_i64(value)
+        } else {
+            self.visitor.visit_i128(value)
+        }
+    }
+
+    /// A string is an other type than a number or a bool, unless the format
+    /// only has text and the text parses exactly.
+    fn coerce_str<E>(self, text: &str) -> Result<V::Value, E>
+    where
+        E: de::Error,
+    {
+        let text_only = self.text_only;
+        match self.coerce {
+            Coerce::Int => match parse_int(text) {
+                Some(value) => {
+                    if !text_only || text.parse::<i128>().is_err() {
+                        record_coercion::<V::Value>(text, &value);
+                    }
+                    self.visit_int(value)
+                },
+                None => self.visitor.visit_str(text),
+            },
+            Coerce::Float => match parse_float(text) {
+                Some(value) => {
+                    if !text_only || text.parse::<f64>().is_err() {
+                        record_coercion::<V::Value>(text, &value);
+                    }
+                    self.visitor.visit_f64(value)
+                },
+                None => self.visitor.visit_str(text),
+            },
+            Coerce::Bool => match parse_bool(text) {
+                Some(value) => {
+                    if !text_only || (text != "true" && text != "false") {
+                        record_coercion::<V::Value>(text, &value);
+                    }
+                    self.visitor.visit_bool(value)
+                },
+                None => self.visitor.visit_str(text),
+            },
+        }
+    }
+
+    fn coerce_uint<E>(self, value: u64) -> Result<V::Value, E>
+    where
+        E: de::Error,
+    {
+        match (self.coerce, value) {
+            (Coerce::Bool, 0) | (Coerce::Bool, 1) => {
+                record_coercion::<V::Value>(&value.to_string(), &(value == 1));
+                self.visitor.visit_bool(value == 1)
+            },
+            _ => self.visitor.visit_u64(value),
+        }
+    }
+}
+
+/// Errors of the wrapped visitor are returned as `Ok(Err(error))`.
+/// `serde-xml-rs` only reads the end tag of an element when the visitor
+/// returns `Ok`, so this keeps the parser in the right place. The error is
+/// returned again by `CaptureDeserializer`.
+pub(super) type Deferred<T> = Result<T, DeferredError>;
+
+fn defer<T, E>(result: Result<T, DeferredError>) -> Result<Deferred<T>, E>
+where
+    E: de::Error,
+{
+    Ok(result)
+}
+
+/// The error of the wrapped visitor. It remembers how it was made, so
+/// `into_error` makes the same error of the format, and the message is the
+/// one the format would give without coercion.
+#[derive(Debug)]
+pub(super) enum DeferredError {
+    Custom(String),
+    InvalidType(String, String),
+    InvalidValue(String, String),
+    InvalidLength(usize, String),
+    UnknownVariant(String, &'static [&'static str]),
+    UnknownField(String, &'static [&'static str]),
+    MissingField(&'static str),
+    DuplicateField(&'static str),
+}
+
+impl DeferredError {
+    pub(super) fn into_error<E>(self) -> E
+    where
+        E: de::Error,
+    {
+        match self {
+            DeferredError::Custom(message) => E::custom(message),
+            DeferredError::InvalidType(unexpected, expected) => E::invalid_type(Unexpected::Other(&unexpected), &expected.as_str()),
+            DeferredError::InvalidValue(unexpected, expected) => E::invalid_value(Unexpected::Other(&unexpected), &expected.as_str()),
+            DeferredError::InvalidLength(len, expected) => E::invalid_length(len, &expected.as_str()),
+            DeferredError::UnknownVariant(variant, expected) => E::unknown_variant(&variant, expected),
+            DeferredError::UnknownField(field, expected) => E::unknown_field(&field, expected),
+            DeferredError::MissingField(field) => E::missing_field(field),
+            DeferredError::DuplicateField(field) => E::duplicate_field(field),
+        }
+    }
+}
+
+impl de::Error for DeferredError {
+    fn custom<T: Display>(msg: T) -> Self {
+        DeferredError::Custom(msg.to_string())
+    }
+
+    fn invalid_type(unexp: Unexpected, exp: &dyn de::Expected) -> Self {
+        DeferredError::InvalidType(unexp.to_string(), exp.to_string())
+    }
+
+    fn invalid_value(unexp: Unexpected, exp: &dyn de::Expected) -> Self {
+        DeferredError::InvalidValue(unexp.to_string(), exp.to_string())
+    }
+
+    fn invalid_length(len: usize, exp: &dyn de::Expected) -> Self {
+        DeferredError::InvalidLength(len, exp.to_string())
+    }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        DeferredError::UnknownVariant(variant.to_string(), expected)
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        DeferredError::UnknownField(field.to_string(), expected)
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        DeferredError::MissingField(field)
+    }
+
+    fn duplicate_field(field: &'static str) -> Self {
+        DeferredError::DuplicateField(field)
+    }
+}
+
+impl Display for DeferredError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let error: de::value::Error = match self {
+            DeferredError::Custom(message) => return formatter.write_str(message),
+            DeferredError::InvalidType(unexpected, expected) => de::Error::invalid_type(Unexpected::Other(unexpected), &expected.as_str()),
+            DeferredError::InvalidValue(unexpected, expected) => de::Error::invalid_value(Unexpected::Other(unexpected), &expected.as_str()),
+            DeferredError::InvalidLength(len, expected) => de::Error::invalid_length(*len, &expected.as_str()),
+            DeferredError::UnknownVariant(variant, expected) => de::Error::unknown_variant(variant, expected),
+            DeferredError::UnknownField(field, expected) => de::Error::unknown_field(field, expected),
+            DeferredError::MissingField(field) => de::Error::missing_field(field),
+            DeferredError::DuplicateField(field) => de::Error::duplicate_field(field),
+        };
+        error.fmt(formatter)
+    }
+}
+
+impl std::error::Error for DeferredError {}
+
+impl<'de, V> Visitor<'de> for CoerceVisitor<V>
+where
+    V: Visitor<'de>,
+{
+    type Value = Deferred<V::Value>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.visitor.expecting(formatter)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        defer(self.visitor.visit_bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match u64::try_from(v) {
+            Ok(v) => defer(self.coerce_uint(v)),
+            Err(_) => defer(self.visitor.visit_i64(v)),
+        }
+    }
+
+    fn visit_i128<E>(self, v: i128) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        defer(self.visitor.visit_i128(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        defer(self.coerce_uint(v))
+    }
+
+    fn visit_u128<E>(self, v: u128) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        defer(self.visitor.visit_u128(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match self.coerce {
+            Coerce::Int if v.is_finite() && v.fract() == 0.0 => {
+                record_coercion::<V::Value>(&format!("{:?}", v), &(v as i128));
+                defer(self.visit_int(v as i128))
+            },
+            _ => defer(self.visitor.visit_f64(v)),
+        }
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        defer(self.coerce_str(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        defer(self.visitor.visit_unit())
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        defer(self.visitor.visit_none())
+    }
+
+    /// `serde-xml-rs` gives an element as `{"$value": "text"}`, the text is
+    /// used and attributes are dropped.
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut value = None;
+        let mut seed = Some(self);
+        while let Some(key) = map.next_key::<String>()? {
+            match (key.as_str(), seed.take()) {
+                ("$value", Some(seed)) => value = Some(map.next_value_seed(seed)?),
+                (_, unused) => {
+                    seed = unused;
+                    map.next_value::<IgnoredAny>()?;
+                },
+            }
+        }
+        match value {
+            Some(value) => Ok(value),
+            None => defer(Err(de::Error::invalid_type(Unexpected::Map, &"a number or a bool"))),
+        }
+    }
+}
+
+impl<'de, V> DeserializeSeed<'de> for CoerceVisitor<V>
+where
+    V: Visitor<'de>,
+{
+    type Value = Deferred<V::Value>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
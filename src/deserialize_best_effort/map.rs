@@ -0,0 +1,71 @@
+// The value of a map field with its own key, ex: `scores: HashMap<String, u32>`
+// for `<scores><a>1</a><b>2</b></scores>`.
+//
+// The map is read entry by entry: an entry that can not be deserialized is
+// recorded in the report and dropped, the other entries are kept. Every entry
+// is given to `add_data` of the field with its own key, so a key that is
+// found again (also in a repeated map) is merged like a repeated key of a
+// struct: `HashMap<String, Vec<T>>` collects all values of the key.
+use std::fmt;
+use std::marker::PhantomData;
+use std::vec;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+
+use super::next_value_or_report;
+
+/// The entries of one map in the order of the input.
+pub struct MapEntries<T>(Vec<(String, T)>);
+
+impl<T> Default for MapEntries<T> {
+    fn default() -> Self {
+        MapEntries(Vec::new())
+    }
+}
+
+impl<T> IntoIterator for MapEntries<T> {
+    type Item = (String, T);
+    type IntoIter = vec::IntoIter<(String, T)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'de, T> Deserialize<'de> for MapEntries<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(MapEntriesVisitor(PhantomData))
+    }
+}
+
+struct MapEntriesVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for MapEntriesVisitor<T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = MapEntries<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if let Some(value) = next_value_or_report(&mut map, &key) {
+                entries.push((key, value));
+            }
+        }
+        Ok(MapEntries(entries))
+    }
+}
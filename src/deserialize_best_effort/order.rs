@@ -0,0 +1,92 @@
+// The order of the keys in the input, for `#[best_effort(ordered)]`.
+//
+// A repeated key adds its value to its own field, so after deserializing it
+// is not known that `<field1>ghi</field1>` came after `<field2>opq</field2>`.
+// The `ordered` field remembers every key with the number of times it was
+// found before, this is the index of the value in a `Vec` field.
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::slice;
+
+/// One key in the input.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OrderEntry {
+    /// The name of the field (after `rename`), or the key itself if it is
+    /// not a field.
+    pub key: String,
+    /// How many times the key was found before, ex: `2` for the third
+    /// `<field1>`.
+    pub index: usize,
+}
+
+/// All keys of a struct in the order of the input.
+/// Example for `test.xml`:
+/// `field1 0, field1 1, field2 0, field2 1, field1 2, field2 2, unknown_tag 0`
+#[derive(Debug, Clone, Default)]
+pub struct DocumentOrder {
+    entries: Vec<OrderEntry>,
+    /// The number of entries of every key, so `push` does not count them.
+    counts: HashMap<String, usize>,
+}
+
+impl DocumentOrder {
+    /// Add `key` after the keys that were found before.
+    pub fn push(&mut self, key: &str) {
+        let count = match self.counts.get_mut(key) {
+            Some(count) => count,
+            None => self.counts.entry(key.to_string()).or_insert(0),
+        };
+        let index = *count;
+        *count += 1;
+        self.entries.push(OrderEntry{ key: key.to_string(), index });
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, OrderEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// How many values of `key` were found.
+    pub fn count(&self, key: &str) -> usize {
+        self.counts.get(key).copied().unwrap_or(0)
+    }
+
+    /// The positions in the document of all values of `key`.
+    pub fn positions<'a>(&'a self, key: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.entries.iter()
+            .enumerate()
+            .filter(move |(_, entry)| entry.key == key)
+            .map(|(position, _)| position)
+    }
+}
+
+/// The counts follow from the entries, so only the entries are compared.
+impl PartialEq for DocumentOrder {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries == other.entries
+    }
+}
+
+impl Eq for DocumentOrder {}
+
+impl Hash for DocumentOrder {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.entries.hash(state);
+    }
+}
+
+impl<'a> IntoIterator for &'a DocumentOrder {
+    type Item = &'a OrderEntry;
+    type IntoIter = slice::Iter<'a, OrderEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
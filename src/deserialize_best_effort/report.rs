@@ -0,0 +1,556 @@
+// Opt-in report of the errors that are swallowed while deserializing.
+//
+// The `Deserialize` implementations can not get extra arguments, so the
+// report is collected in a thread local. It is only active inside
+// `with_report` (or `from_reader_with_report`), otherwise nothing is recorded.
+use std::any::type_name;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::Read;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserialize, DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+
+use super::coerce::{Coerce, CoerceVisitor, DeferredError, is_text_only};
+use super::scope::with_scoped;
+use super::strict;
+use super::unwrap_text_nodes;
+
+/// A value that could not be deserialized and was replaced by its default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BestEffortIssue {
+    /// Path of the field, ex: `payment.card.number`
+    pub path: String,
+    /// The value from the input, if the format gave it before failing.
+    pub raw: Option<String>,
+    /// The type the value was deserialized into.
+    pub expected: String,
+    /// The error message from serde.
+    pub message: String,
+}
+
+/// All issues found while deserializing one document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BestEffortReport {
+    pub issues: Vec<BestEffortIssue>,
+    /// Values that were parsed leniently, ex: `" 12 "`, `"12.0"` or `"yes"`.
+    /// The `message` is the value that was used.
+    pub coercions: Vec<BestEffortIssue>,
+}
+
+impl BestEffortReport {
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty() && self.coercions.is_empty()
+    }
+}
+
+impl fmt::Display for BestEffortReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for issue in &self.issues {
+            write!(f, "{}: expected `{}`", issue.path, issue.expected)?;
+            if let Some(raw) = &issue.raw {
+                write!(f, ", found {:?}", raw)?;
+            }
+            writeln!(f, " ({})", issue.message)?;
+        }
+        for coercion in &self.coercions {
+            writeln!(f, "{}: coerced {:?} to `{}` as `{}`",
+                coercion.path, coercion.raw.as_deref().unwrap_or_default(), coercion.message, coercion.expected)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct ReportState {
+    path: Vec<String>,
+    raw: Option<String>,
+    issues: Vec<BestEffortIssue>,
+    coercions: Vec<BestEffortIssue>,
+}
+
+thread_local! {
+    static REPORT: RefCell<Option<ReportState>> = const { RefCell::new(None) };
+}
+
+/// Run `f` and collect all swallowed errors while it runs.
+pub fn with_report<T, F>(f: F) -> (T, BestEffortReport)
+where
+    F: FnOnce() -> T,
+{
+    let (value, state) = with_scoped(&REPORT, ReportState::default(), f);
+    let state = state.unwrap_or_default();
+    (value, BestEffortReport{ issues: state.issues, coercions: state.coercions })
+}
+
+/// Read an xml file like `serde_xml_rs::from_reader` and also return the
+/// report of all values that were replaced by their default.
+pub fn from_reader_with_report<R, T>(reader: R) -> Result<(T, BestEffortReport), serde_xml_rs::Error>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    let (parsed_object, report) = with_report(|| serde_xml_rs::from_reader(reader));
+    Ok((parsed_object?, report))
+}
+
+fn set_raw(raw: String) {
+    REPORT.with(|report| {
+        if let Some(state) = report.borrow_mut().as_mut() {
+            state.raw = Some(raw);
+        }
+    });
+}
+
+fn record<T>(key: &str, message: &dyn fmt::Display) {
+    strict::record_error(message);
+    REPORT.with(|report| {
+        if let Some(state) = report.borrow_mut().as_mut() {
+            let mut path = state.path.clone();
+            if !key.is_empty() {
+                path.push(key.to_string());
+            }
+            let raw = state.raw.take();
+            state.issues.push(BestEffortIssue{
+                path: path.join("."),
+                raw,
+                expected: type_name::<T>().to_string(),
+                message: message.to_string(),
+            });
+        }
+    });
+}
+
+/// Record a value that was read but has no place in the field, like a value
+/// past the end of an array.
+pub(super) fn record_dropped<T>(key: &str, message: &dyn fmt::Display) {
+    set_raw_none();
+    record::<T>(key, message);
+}
+
+/// Record a value that was parsed leniently, see `coerce.rs`.
+pub(super) fn record_coercion<T>(raw: &str, value: &dyn fmt::Display) {
+    strict::record_coercion::<T>(raw);
+    REPORT.with(|report| {
+        if let Some(state) = report.borrow_mut().as_mut() {
+            state.coercions.push(BestEffortIssue{
+                path: state.path.join("."),
+                raw: Some(raw.to_string()),
+                expected: type_name::<T>().to_string(),
+                message: value.to_string(),
+            });
+        }
+    });
+}
+
+/// Adds `key` to the path while the value of that key is deserialized.
+struct PathGuard(bool);
+
+impl PathGuard {
+    fn enter(key: &str) -> Self {
+        PathGuard(REPORT.with(|report| {
+            match report.borrow_mut().as_mut() {
+                Some(state) => {
+                    state.path.push(key.to_string());
+                    state.raw = None;
+                    true
+                },
+                None => false,
+            }
+        }))
+    }
+}
+
+impl Drop for PathGuard {
+    fn drop(&mut self) {
+        if self.0 {
+            REPORT.with(|report| {
+                if let Some(state) = report.borrow_mut().as_mut() {
+                    state.path.pop();
+                }
+            });
+        }
+    }
+}
+
+/// Used instead of `map.next_value().unwrap_or_default()`.
+/// If the value can not be deserialized it is recorded in the report.
+pub fn next_value_or_default<'de, A, T>(map: &mut A, key: &str) -> T
+where
+    A: MapAccess<'de>,
+    T: Deserialize<'de> + Default,
+{
+    next_value_or_report(map, key).unwrap_or_default()
+}
+
+/// Like `next_value_or_default`, but returns `None` instead of the default,
+/// for types that do not implement `Default`.
+pub fn next_value_or_report<'de, A, T>(map: &mut A, key: &str) -> Option<T>
+where
+    A: MapAccess<'de>,
+    T: Deserialize<'de>,
+{
+    let guard = PathGuard::enter(key);
+    match map.next_value_seed(CaptureSeed(PhantomData)) {
+        Ok(next_value) => Some(next_value),
+        Err(err) => {
+            drop(guard);
+            record::<T>(key, &err);
+            None
+        },
+    }
+}
+
+/// Used for `#[best_effort(required)]` fields: a value that can not be
+/// deserialized is an error, like a missing key.
+pub fn next_value_required<'de, A, T>(map: &mut A, key: &str) -> Result<T, A::Error>
+where
+    A: MapAccess<'de>,
+    T: Deserialize<'de>,
+{
+    let _guard = PathGuard::enter(key);
+    map.next_value_seed(CaptureSeed(PhantomData))
+}
+
+/// Used for `#[serde(deserialize_with = "path")]`.
+/// The value is read as a `serde_json::Value` (without the `$value` objects
+/// of `serde-xml-rs`) and given to `deserialize_with`. Returns `None` if the
+/// value can not be read or `deserialize_with` fails.
+pub fn next_value_with<'de, A, T, E, F>(map: &mut A, key: &str, deserialize_with: F) -> Option<T>
+where
+    A: MapAccess<'de>,
+    F: FnOnce(Value) -> Result<T, E>,
+    E: fmt::Display,
+{
+    let value = next_value_or_report(map, key)?;
+    call_deserialize_with(value, key, deserialize_with)
+}
+
+/// Like `next_value_with`, for an element of a sequence.
+/// Returns `None` at the end of the sequence, like `next_element_or_report`.
+pub fn next_element_with<'de, A, T, E, F>(seq: &mut A, index: usize, deserialize_with: F) -> Option<Option<T>>
+where
+    A: SeqAccess<'de>,
+    F: FnOnce(Value) -> Result<T, E>,
+    E: fmt::Display,
+{
+    let key = index.to_string();
+    let value = next_element_or_report(seq, index)?;
+    Some(value.and_then(|value| call_deserialize_with(value, &key, deserialize_with)))
+}
+
+fn call_deserialize_with<T, E, F>(value: Value, key: &str, deserialize_with: F) -> Option<T>
+where
+    F: FnOnce(Value) -> Result<T, E>,
+    E: fmt::Display,
+{
+    let value = unwrap_text_nodes(value);
+    let raw = match &value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    };
+    match deserialize_with(value) {
+        Ok(next_value) => Some(next_value),
+        Err(err) => {
+            set_raw(raw);
+            record::<T>(key, &err);
+            None
+        },
+    }
+}
+
+/// Used instead of `seq.next_element().unwrap_or_default().unwrap_or_default()`.
+/// A missing element is not an issue, an element that can not be
+/// deserialized is recorded in the report.
+pub fn next_element_or_default<'de, A, T>(seq: &mut A, index: usize) -> T
+where
+    A: SeqAccess<'de>,
+    T: Deserialize<'de> + Default,
+{
+    next_element_or_report(seq, index).flatten().unwrap_or_default()
+}
+
+/// Like `next_element_or_default`, for types that do not implement `Default`.
+/// Returns `None` at the end of the sequence and `Some(None)` if the element
+/// can not be deserialized (this is recorded in the report).
+pub fn next_element_or_report<'de, A, T>(seq: &mut A, index: usize) -> Option<Option<T>>
+where
+    A: SeqAccess<'de>,
+    T: Deserialize<'de>,
+{
+    let key = index.to_string();
+    let guard = PathGuard::enter(&key);
+    match seq.next_element_seed(CaptureSeed(PhantomData)) {
+        Ok(next_element) => next_element.map(Some),
+        Err(err) => {
+            drop(guard);
+            record::<T>(&key, &err);
+            Some(None)
+        },
+    }
+}
+
+/// Used instead of `T::deserialize(deserializer).unwrap_or_default()`.
+pub fn deserialize_or_default<'de, D, T>(deserializer: D, key: &str) -> T
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    let guard = PathGuard::enter(key);
+    match CaptureSeed(PhantomData).deserialize(deserializer) {
+        Ok(value) => value,
+        Err(err) => {
+            drop(guard);
+            record::<T>(key, &err);
+            T::default()
+        },
+    }
+}
+
+/// Used instead of `result.unwrap_or_default()` when there is no
+/// deserializer to read the raw value from.
+pub fn unwrap_or_report<T, E>(result: Result<T, E>, key: &str) -> T
+where
+    T: Default,
+    E: fmt::Display,
+{
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            set_raw_none();
+            record::<T>(key, &err);
+            T::default()
+        },
+    }
+}
+
+fn set_raw_none() {
+    REPORT.with(|report| {
+        if let Some(state) = report.borrow_mut().as_mut() {
+            state.raw = None;
+        }
+    });
+}
+
+/// Deserializes `T`, numbers and bools are parsed leniently (see `coerce.rs`).
+/// When a report is active it also remembers the last scalar the format
+/// gave, so it can be added to the issue.
+struct CaptureSeed<T>(PhantomData<T>);
+
+impl<'de, T> DeserializeSeed<'de> for CaptureSeed<T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = T;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize(CaptureDeserializer(deserializer))
+    }
+}
+
+struct CaptureDeserializer<D>(D);
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error>
+            where
+                V: Visitor<'de>,
+            {
+                self.0.$method($($arg,)* CaptureVisitor(visitor))
+            }
+        )*
+    };
+}
+
+// Numbers and bools of a human readable format are read with
+// `deserialize_any`, so text can be coerced. Other formats (bincode) only know
+// the type from the method that is called.
+macro_rules! coerce_deserialize {
+    ($($method:ident($coerce:expr))*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, D::Error>
+            where
+                V: Visitor<'de>,
+            {
+                if !self.0.is_human_readable() {
+                    return self.0.$method(CaptureVisitor(visitor));
+                }
+                self.0.deserialize_any(CoerceVisitor::new(CaptureVisitor(visitor), $coerce, is_text_only::<D>()))?
+                    .map_err(DeferredError::into_error)
+            }
+        )*
+    };
+}
+
+impl<'de, D> Deserializer<'de> for CaptureDeserializer<D>
+where
+    D: Deserializer<'de>,
+{
+    type Error = D::Error;
+
+    coerce_deserialize! {
+        deserialize_bool(Coerce::Bool)
+        deserialize_i8(Coerce::Int)
+        deserialize_i16(Coerce::Int)
+        deserialize_i32(Coerce::Int)
+        deserialize_i64(Coerce::Int)
+        deserialize_i128(Coerce::Int)
+        deserialize_u8(Coerce::Int)
+        deserialize_u16(Coerce::Int)
+        deserialize_u32(Coerce::Int)
+        deserialize_u64(Coerce::Int)
+        deserialize_u128(Coerce::Int)
+        deserialize_f32(Coerce::Float)
+        deserialize_f64(Coerce::Float)
+    }
+
+    forward_deserialize! {
+        deserialize_any()
+        deserialize_char()
+        deserialize_str()
+        deserialize_string()
+        deserialize_bytes()
+        deserialize_byte_buf()
+        deserialize_option()
+        deserialize_unit()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_seq()
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_map()
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_identifier()
+        deserialize_ignored_any()
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+struct CaptureVisitor<V>(V);
+
+macro_rules! capture_visit {
+    ($($method:ident($ty:ty))*) => {
+        $(
+            fn $method<E>(self, v: $ty) -> Result<V::Value, E>
+            where
+                E: de::Error,
+            {
+                set_raw(v.to_string());
+                self.0.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, V> Visitor<'de> for CaptureVisitor<V>
+where
+    V: Visitor<'de>,
+{
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.0.expecting(formatter)
+    }
+
+    capture_visit! {
+        visit_bool(bool)
+        visit_i8(i8)
+        visit_i16(i16)
+        visit_i32(i32)
+        visit_i64(i64)
+        visit_i128(i128)
+        visit_u8(u8)
+        visit_u16(u16)
+        visit_u32(u32)
+        visit_u64(u64)
+        visit_u128(u128)
+        visit_f32(f32)
+        visit_f64(f64)
+        visit_char(char)
+        visit_str(&str)
+        visit_borrowed_str(&'de str)
+        visit_string(String)
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<V::Value, E>
+    where
+        E: de::Error,
+    {
+        set_raw(String::from_utf8_lossy(v).into_owned());
+        self.0.visit_bytes(v)
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<V::Value, E>
+    where
+        E: de::Error,
+    {
+        set_raw(String::from_utf8_lossy(v).into_owned());
+        self.0.visit_borrowed_bytes(v)
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<V::Value, E>
+    where
+        E: de::Error,
+    {
+        set_raw(String::from_utf8_lossy(&v).into_owned());
+        self.0.visit_byte_buf(v)
+    }
+
+    fn visit_none<E>(self) -> Result<V::Value, E>
+    where
+        E: de::Error,
+    {
+        self.0.visit_none()
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<V::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0.visit_some(deserializer)
+    }
+
+    fn visit_unit<E>(self) -> Result<V::Value, E>
+    where
+        E: de::Error,
+    {
+        self.0.visit_unit()
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<V::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0.visit_newtype_struct(deserializer)
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<V::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.0.visit_seq(seq)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<V::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.0.visit_map(map)
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<V::Value, A::Error>
+    where
+        A: de::EnumAccess<'de>,
+    {
+        self.0.visit_enum(data)
+    }
+}
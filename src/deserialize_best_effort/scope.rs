@@ -0,0 +1,36 @@
+// State that is only set while a function runs, for the report and for strict
+// mode.
+//
+// The `Deserialize` implementations can not get extra arguments, so the state
+// is kept in a thread local. `with_scoped` sets it for the time `f` runs and
+// puts the previous state back after it, so the calls can be nested.
+use std::cell::RefCell;
+use std::thread::LocalKey;
+
+pub(super) type Scoped<S> = LocalKey<RefCell<Option<S>>>;
+
+/// Run `f` with `state` set in `key`. Returns the result of `f` and the state
+/// after it ran.
+pub(super) fn with_scoped<S, T, F>(key: &'static Scoped<S>, state: S, f: F) -> (T, Option<S>)
+where
+    S: 'static,
+    F: FnOnce() -> T,
+{
+    // Restores the previous state, also when `f` panics.
+    struct Restore<S: 'static> {
+        key: &'static Scoped<S>,
+        previous: Option<S>,
+    }
+    impl<S> Drop for Restore<S> {
+        fn drop(&mut self) {
+            let previous = self.previous.take();
+            self.key.with(|cell| *cell.borrow_mut() = previous);
+        }
+    }
+
+    let restore = Restore{ key, previous: key.with(|cell| cell.replace(Some(state))) };
+    let value = f();
+    let state = key.with(|cell| cell.replace(None));
+    drop(restore);
+    (value, state)
+}
@@ -0,0 +1,469 @@
+// Serializing the types of `#[derive(SerializeBestEffort)]`.
+//
+// This writes a struct back in the form it was read in: every value that
+// `add_data` got is written with the key of the field, so a `Vec` is written
+// as a repeated key (like `<field1>abc</field1><field1>def</field1>`), and the
+// values of the catch-all field are written with their own key.
+//
+// With a `DocumentOrder` the values are written in the order of the input:
+// for every `OrderEntry` the value with that index is written
+// (`write_entry_at`), the values that were not in the input (added after
+// deserializing) are written after them (`serialize_remaining_entries`).
+// `write_entry_at` keeps a cursor in the values of every field, that moves
+// forward while the order is walked, so every value is only visited once.
+//
+// A struct is written with `serialize_struct`, so `serde-xml-rs` writes the
+// root element. The keys of the catch-all field are only known at runtime and
+// `SerializeStruct` only takes a `&'static str`, so a struct with a catch-all
+// or flattened field is written as a map (`serialize_struct_map`).
+use std::any::type_name;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::iter;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use serde::ser::{self, Serialize, SerializeMap, SerializeStruct, Serializer};
+use serde_json::{Map, Value};
+
+use super::{DocumentOrder, UnknownFields};
+
+/// The serializing side of `DeserializeBestEffortTypes`.
+pub trait SerializeBestEffortTypes {
+    /// One value, like the value `add_data` gets.
+    type Value: Serialize + ?Sized;
+
+    /// All values of the field in the order they were added. A list has a
+    /// value for every element, `None` has no value.
+    fn values(&self) -> Box<dyn Iterator<Item = &Self::Value> + '_>;
+}
+
+/// Types that hold one value.
+macro_rules! impl_one_value {
+    ($($type_:ty),* $(,)?) => {
+        $(
+            impl SerializeBestEffortTypes for $type_ {
+                type Value = $type_;
+
+                fn values(&self) -> Box<dyn Iterator<Item = &Self::Value> + '_> {
+                    Box::new(iter::once(self))
+                }
+            }
+        )*
+    };
+}
+
+impl_one_value!(
+    i8, i16, i32, i64, i128, isize,
+    u8, u16, u32, u64, u128, usize,
+    f32, f64,
+    bool, char,
+    String, str,
+    Value,
+);
+
+impl SerializeBestEffortTypes for &str {
+    type Value = str;
+
+    fn values(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        Box::new(iter::once(*self))
+    }
+}
+
+/// Lists and sets have a value for every element.
+macro_rules! impl_list_values {
+    ($($type_:ident $(+ $bound:path)*),* $(,)?) => {
+        $(
+            impl<T> SerializeBestEffortTypes for $type_<T> where
+                T: Serialize $(+ $bound)* {
+                type Value = T;
+
+                fn values(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+                    Box::new(self.iter())
+                }
+            }
+        )*
+    };
+}
+
+impl_list_values!(Vec, VecDeque, BTreeSet + Ord, HashSet + Eq + std::hash::Hash);
+
+/// Elements that are still `None` are empty, see `add_data`.
+impl<T,const N: usize> SerializeBestEffortTypes for [Option<T>; N] where
+    T: Serialize {
+    type Value = T;
+
+    fn values(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        Box::new(self.iter().flatten())
+    }
+}
+
+/// Wrappers have the values of the type they wrap (`Box<str>` has `str`).
+macro_rules! impl_wrapper_values {
+    ($($type_:ident),* $(,)?) => {
+        $(
+            impl<T> SerializeBestEffortTypes for $type_<T> where
+                T: SerializeBestEffortTypes + ?Sized {
+                type Value = T::Value;
+
+                fn values(&self) -> Box<dyn Iterator<Item = &Self::Value> + '_> {
+                    (**self).values()
+                }
+            }
+        )*
+    };
+}
+
+impl_wrapper_values!(Box, Rc, Arc);
+
+impl<'a,B> SerializeBestEffortTypes for Cow<'a,B> where
+    B: ToOwned + SerializeBestEffortTypes + ?Sized {
+    type Value = B::Value;
+
+    fn values(&self) -> Box<dyn Iterator<Item = &Self::Value> + '_> {
+        (**self).values()
+    }
+}
+
+/// `None` has no value, so it is not written.
+impl<T> SerializeBestEffortTypes for Option<T> where
+    T: SerializeBestEffortTypes {
+    type Value = T::Value;
+
+    fn values(&self) -> Box<dyn Iterator<Item = &Self::Value> + '_> {
+        match self {
+            Some(value) => value.values(),
+            None => Box::new(iter::empty()),
+        }
+    }
+}
+
+/// A map field with its own key has one value, the map. An empty map has no
+/// value, so it is not written. The derive writes it with `MapValues`.
+/// (The catch-all field is written with `SerializeBestEffortCatchAll`.)
+macro_rules! impl_map_values {
+    ($($type_:ident),* $(,)?) => {
+        $(
+            impl<V> SerializeBestEffortTypes for $type_<String, V> where
+                V: Serialize {
+                type Value = Self;
+
+                fn values(&self) -> Box<dyn Iterator<Item = &Self> + '_> {
+                    Box::new(Some(self).filter(|map| !map.is_empty()).into_iter())
+                }
+            }
+        )*
+    };
+}
+
+impl_map_values!(HashMap, BTreeMap);
+
+impl SerializeBestEffortTypes for Map<String, Value> {
+    type Value = Self;
+
+    fn values(&self) -> Box<dyn Iterator<Item = &Self> + '_> {
+        Box::new(Some(self).filter(|map| !map.is_empty()).into_iter())
+    }
+}
+
+/// Writes a map field like it was read: every value of an entry with the key
+/// of the entry, so `HashMap<String, Vec<T>>` writes a repeated key.
+pub struct MapValues<'a, M: ?Sized>(pub &'a M);
+
+impl<'a, M, V> Serialize for MapValues<'a, M> where
+    M: ?Sized,
+    &'a M: IntoIterator<Item = (&'a String, &'a V)>,
+    V: SerializeBestEffortTypes + 'a {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        for (key, values) in self.0 {
+            for value in values.values() {
+                SerializeMap::serialize_entry(&mut map, key, value)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// The catch-all field: every value with its own key.
+pub trait SerializeBestEffortCatchAll {
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &Value)> + '_>;
+
+    /// Write the values that are not in `order`.
+    fn serialize_remaining_entries<M>(&self, order: &DocumentOrder, map: &mut M) -> Result<(), M::Error>
+    where
+        M: SerializeEntries,
+    {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for (key, value) in self.entries() {
+            let count = counts.entry(key).or_insert(0);
+            if *count >= order.count(key) {
+                serialize_unknown_entry(map, key, value)?;
+            }
+            *count += 1;
+        }
+        Ok(())
+    }
+}
+
+impl<V> SerializeBestEffortCatchAll for HashMap<String, V> where
+    V: SerializeBestEffortTypes<Value = Value> {
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &Value)> + '_> {
+        Box::new(self.iter().flat_map(|(key, value)| value.values().map(move |value| (key.as_str(), value))))
+    }
+}
+
+impl<V> SerializeBestEffortCatchAll for BTreeMap<String, V> where
+    V: SerializeBestEffortTypes<Value = Value> {
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &Value)> + '_> {
+        Box::new(self.iter().flat_map(|(key, value)| value.values().map(move |value| (key.as_str(), value))))
+    }
+}
+
+impl SerializeBestEffortCatchAll for Map<String, Value> {
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &Value)> + '_> {
+        Box::new(self.iter().map(|(key, value)| (key.as_str(), value)))
+    }
+}
+
+impl SerializeBestEffortCatchAll for UnknownFields {
+    fn entries(&self) -> Box<dyn Iterator<Item = (&str, &Value)> + '_> {
+        Box::new(self.iter().map(|field| (field.key.as_str(), &field.value)))
+    }
+}
+
+/// Where the keys of a struct are written: the `SerializeStruct` of the struct
+/// (see `StructEntries`), or a `SerializeMap`.
+pub trait SerializeEntries {
+    type Error: ser::Error;
+
+    /// Write a key that is only known at runtime, like a key of the
+    /// catch-all field.
+    fn serialize_entry<V>(&mut self, key: &str, value: &V) -> Result<(), Self::Error>
+    where
+        V: Serialize + ?Sized;
+
+    /// Write the key of a field.
+    fn serialize_field<V>(&mut self, key: &'static str, value: &V) -> Result<(), Self::Error>
+    where
+        V: Serialize + ?Sized,
+    {
+        self.serialize_entry(key, value)
+    }
+}
+
+impl<M> SerializeEntries for M where
+    M: SerializeMap {
+    type Error = M::Error;
+
+    fn serialize_entry<V>(&mut self, key: &str, value: &V) -> Result<(), M::Error>
+    where
+        V: Serialize + ?Sized,
+    {
+        check_attribute_key::<M, M::Error>(key)?;
+        SerializeMap::serialize_entry(self, key, value)
+    }
+}
+
+/// Writes the keys as fields of a struct. Only a struct without a catch-all
+/// or flattened field is written this way, see `serialize_struct_map`.
+pub struct StructEntries<S>(pub S);
+
+impl<S> StructEntries<S> where
+    S: SerializeStruct {
+    pub fn end(self) -> Result<S::Ok, S::Error> {
+        self.0.end()
+    }
+}
+
+impl<S> SerializeEntries for StructEntries<S> where
+    S: SerializeStruct {
+    type Error = S::Error;
+
+    fn serialize_entry<V>(&mut self, key: &str, _value: &V) -> Result<(), S::Error>
+    where
+        V: Serialize + ?Sized,
+    {
+        Err(ser::Error::custom(format_args!("`{}` is not a field of the struct", key)))
+    }
+
+    fn serialize_field<V>(&mut self, key: &'static str, value: &V) -> Result<(), S::Error>
+    where
+        V: Serialize + ?Sized,
+    {
+        check_attribute_key::<S, S::Error>(key)?;
+        self.0.serialize_field(key, value)
+    }
+}
+
+/// `serde-xml-rs` 0.4 can not write attributes and would write `@id` as an
+/// element name, so an attribute key is an error there.
+fn check_attribute_key<M, E>(key: &str) -> Result<(), E>
+where
+    E: ser::Error,
+{
+    if key.starts_with('@') && is_xml::<M>() {
+        return Err(E::custom(format_args!("serde-xml-rs can not write the attribute `{}`", key)));
+    }
+    Ok(())
+}
+
+/// Write a struct with a catch-all or flattened field. `SerializeStruct` only
+/// takes `&'static str` keys, so like serde's own derive the struct is written
+/// as a map. `serde-xml-rs` writes no root element for a map, there the map is
+/// wrapped in a newtype variant with the name of the struct, that it writes as
+/// `<name>...</name>` like a struct.
+pub fn serialize_struct_map<S, T>(serializer: S, name: &'static str, value: &T) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: SerializeBestEffortFlatten + ?Sized,
+{
+    let entries = FlattenEntries(value);
+    if is_xml::<S>() {
+        serializer.serialize_newtype_variant(name, 0, name, &entries)
+    } else {
+        entries.serialize(serializer)
+    }
+}
+
+/// `true` for the serializer of `serde-xml-rs` (or its `SerializeMap` and
+/// `SerializeStruct`), recognized by its type name like in `is_text_only`.
+fn is_xml<S>() -> bool {
+    type_name::<S>().contains("serde_xml_rs::")
+}
+
+/// The entries of a struct as a map.
+struct FlattenEntries<'a, T: ?Sized>(&'a T);
+
+impl<'a, T> Serialize for FlattenEntries<'a, T> where
+    T: SerializeBestEffortFlatten + ?Sized {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        self.0.serialize_entries(&mut map)?;
+        map.end()
+    }
+}
+
+/// Writes the value number `index` of `key` and returns `true`, or returns
+/// `false` if no field has such a value. See `write_entry_at`.
+pub type WriteEntryAt<'a, M> = Box<dyn FnMut(&str, usize, &mut M) -> Result<bool, <M as SerializeEntries>::Error> + 'a>;
+
+/// A struct that can be used with `#[serde(flatten)]` in a struct that
+/// derives `SerializeBestEffort`, its keys are written in the same map.
+pub trait SerializeBestEffortFlatten {
+    /// Write all values, in the order of the `ordered` field if the struct
+    /// has one.
+    fn serialize_entries<M>(&self, map: &mut M) -> Result<(), M::Error>
+    where
+        M: SerializeEntries;
+
+    /// Write the values in the order of a `DocumentOrder`. The cursors only
+    /// move forward, so the index of a key has to go up like in the order.
+    fn write_entry_at<'a, M>(&'a self) -> WriteEntryAt<'a, M>
+    where
+        M: SerializeEntries + 'a;
+
+    /// Write the values that are not in `order`.
+    fn serialize_remaining_entries<M>(&self, order: &DocumentOrder, map: &mut M) -> Result<(), M::Error>
+    where
+        M: SerializeEntries;
+}
+
+/// The values of a field, for `write_entry_at`.
+pub struct ValuesCursor<I> {
+    values: I,
+    next_index: usize,
+}
+
+impl<I> ValuesCursor<I> where
+    I: Iterator {
+    pub fn new(values: I) -> Self {
+        ValuesCursor{ values, next_index: 0 }
+    }
+
+    /// The value number `index`, values before it are skipped. `None` if
+    /// there is no such value, or it was already given.
+    pub fn value_at(&mut self, index: usize) -> Option<I::Item> {
+        if index < self.next_index {
+            return None;
+        }
+        let value = self.values.nth(index - self.next_index);
+        self.next_index = index + 1;
+        value
+    }
+}
+
+/// The values of the catch-all field by key, for `write_entry_at`.
+pub struct EntriesCursor<'a> {
+    values: HashMap<&'a str, ValuesCursor<std::vec::IntoIter<&'a Value>>>,
+}
+
+impl<'a> EntriesCursor<'a> {
+    pub fn new<C>(catch_all: &'a C) -> Self where
+        C: SerializeBestEffortCatchAll + ?Sized {
+        let mut values: HashMap<&str, Vec<&Value>> = HashMap::new();
+        for (key, value) in catch_all.entries() {
+            values.entry(key).or_default().push(value);
+        }
+        let values = values.into_iter()
+            .map(|(key, values)| (key, ValuesCursor::new(values.into_iter())))
+            .collect();
+        EntriesCursor{ values }
+    }
+
+    /// Write the value number `index` of `key`, returns `false` if there is
+    /// no such value.
+    pub fn write_entry_at<M>(&mut self, key: &str, index: usize, map: &mut M) -> Result<bool, M::Error>
+    where
+        M: SerializeEntries,
+    {
+        match self.values.get_mut(key).and_then(|values| values.value_at(index)) {
+            Some(value) => {
+                serialize_unknown_entry(map, key, value)?;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+}
+
+/// Write a value of the catch-all field like it was in the input.
+/// The `{"$value": "text"}` objects of `serde-xml-rs` are written as the text
+/// itself. Attributes are written with their `@name` key, see
+/// `WithAttributes`.
+pub fn serialize_unknown_entry<M>(map: &mut M, key: &str, value: &Value) -> Result<(), M::Error>
+where
+    M: SerializeEntries,
+{
+    map.serialize_entry(key, &OriginalValue(value))
+}
+
+struct OriginalValue<'a>(&'a Value);
+
+impl<'a> Serialize for OriginalValue<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0 {
+            Value::Object(object) => match object.get("$value") {
+                Some(text) if object.len() == 1 => OriginalValue(text).serialize(serializer),
+                _ => {
+                    let mut map = serializer.serialize_map(None)?;
+                    for (key, value) in object {
+                        serialize_unknown_entry(&mut map, key, value)?;
+                    }
+                    map.end()
+                },
+            },
+            Value::Array(list) => serializer.collect_seq(list.iter().map(OriginalValue)),
+            value => value.serialize(serializer),
+        }
+    }
+}
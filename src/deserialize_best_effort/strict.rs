@@ -0,0 +1,153 @@
+// Opt-in strict mode, to validate the input with the same types.
+//
+// Like the report, the mode is kept in a thread local because the
+// `Deserialize` implementations can not get extra arguments. Inside
+// `with_strict` (or `Strict<T>`) the derived implementations return the first
+// error instead of using the default, and return an error for unknown keys
+// and for repeated keys of fields that would only keep the last value.
+use std::any::type_name;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::Read;
+use std::ops::{Deref, DerefMut};
+
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer, IgnoredAny, SeqAccess};
+
+use super::DeserializeBestEffortTypes;
+use super::report::with_report;
+use super::scope::with_scoped;
+
+#[derive(Default)]
+struct StrictState {
+    /// The first error, it is returned by the derived `Deserialize` that is
+    /// being deserialized.
+    error: Option<String>,
+}
+
+thread_local! {
+    static STRICT: RefCell<Option<StrictState>> = const { RefCell::new(None) };
+}
+
+/// Run `f` in strict mode.
+pub fn with_strict<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    with_scoped(&STRICT, StrictState::default(), f).0
+}
+
+/// Read an xml file like `serde_xml_rs::from_reader`, in strict mode.
+pub fn from_reader_strict<R, T>(reader: R) -> Result<T, serde_xml_rs::Error>
+where
+    R: Read,
+    T: DeserializeOwned,
+{
+    serde_xml_rs::from_reader(reader).map(|Strict(value)| value)
+}
+
+/// Deserializes `T` in strict mode, ex: `serde_json::from_str::<Strict<Root>>(...)`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Strict<T>(pub T);
+
+impl<T> Strict<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Strict<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Strict<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Strict<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        with_strict(|| strict_result(T::deserialize(deserializer))).map(Strict)
+    }
+}
+
+pub fn is_strict() -> bool {
+    STRICT.with(|strict| strict.borrow().is_some())
+}
+
+/// Remember the first error that was swallowed, see `report.rs`.
+pub(super) fn record_error(message: &dyn fmt::Display) {
+    STRICT.with(|strict| {
+        if let Some(state) = strict.borrow_mut().as_mut() {
+            if state.error.is_none() {
+                state.error = Some(message.to_string());
+            }
+        }
+    });
+}
+
+/// A value that was parsed leniently is an error in strict mode.
+pub(super) fn record_coercion<T>(raw: &str) {
+    if is_strict() {
+        record_error(&format_args!("invalid value: {:?}, expected {}", raw, type_name::<T>()));
+    }
+}
+
+/// Used by the derived `Deserialize` on its result. In strict mode the first
+/// error that was swallowed while it was deserialized is returned instead.
+pub fn strict_result<T, E>(result: Result<T, E>) -> Result<T, E>
+where
+    E: de::Error,
+{
+    let error = STRICT.with(|strict| strict.borrow_mut().as_mut().and_then(|state| state.error.take()));
+    match error {
+        Some(error) => Err(E::custom(error)),
+        None => result,
+    }
+}
+
+/// Deserializes with `f` in strict mode, without adding to the report.
+/// Used to find the newtype variant of an untagged enum that matches the
+/// value: a variant only matches if no error was swallowed.
+pub fn try_strict<T, E, F>(f: F) -> Result<T, E>
+where
+    E: de::Error,
+    F: FnOnce() -> Result<T, E>,
+{
+    with_report(|| with_strict(|| strict_result(f()))).0
+}
+
+/// `false` if a repeated key would replace the value of `field`.
+/// Used for the strict check of repeated keys.
+pub fn keeps_values<'de, F, T>(field: &F, _next_value: &T) -> bool
+where
+    F: DeserializeBestEffortTypes<'de, T>,
+    T: DeserializeBestEffortTypes<'de, T>,
+{
+    field.keeps_values()
+}
+
+/// Read the elements after the last field. In strict mode an extra element
+/// is an error, otherwise they are dropped.
+pub fn skip_remaining_elements<'de, A>(seq: &mut A, len: usize, expected: &dyn de::Expected) -> Result<(), A::Error>
+where
+    A: SeqAccess<'de>,
+{
+    if is_strict() {
+        if let Some(IgnoredAny) = seq.next_element()? {
+            return Err(de::Error::invalid_length(len + 1, expected));
+        }
+    }
+    while let Ok(Some(IgnoredAny)) = seq.next_element() {}
+    Ok(())
+}
@@ -0,0 +1,913 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde::de;
+use serde_json::Value;
+use custom_derive::{DeserializeBestEffort, SerializeBestEffort};
+
+use crate::deserialize_best_effort;
+use deserialize_best_effort::{DocumentOrder, Strict, with_report};
+
+#[derive(DeserializeBestEffort, Serialize, Debug, PartialEq)]
+struct Nums {
+    a: u32,
+    b: i64,
+    c: bool,
+    d: f64,
+}
+
+fn from_xml<T: de::DeserializeOwned>(xml: &str) -> T {
+    serde_xml_rs::from_reader(xml.as_bytes()).unwrap()
+}
+
+#[test]
+fn coerces_text() {
+    let xml = "<nums><a> 1,234 </a><b>-0x1F</b><c>Yes</c><d>2_000.5</d></nums>";
+    let (nums, report) = with_report(|| from_xml::<Nums>(xml));
+    assert_eq!(nums, Nums{ a: 1234, b: -31, c: true, d: 2000.5 });
+    assert!(report.issues.is_empty());
+    assert_eq!(report.coercions.len(), 4);
+    assert_eq!(report.coercions[0].path, "a");
+    assert_eq!(report.coercions[0].raw.as_deref(), Some("1,234"));
+}
+
+#[test]
+fn coerces_json_numbers() {
+    let json = r#"{"a": 12.0, "b": "7", "c": 1, "d": 3}"#;
+    let (nums, report) = with_report(|| serde_json::from_str::<Nums>(json).unwrap());
+    assert_eq!(nums, Nums{ a: 12, b: 7, c: true, d: 3.0 });
+    let raw: Vec<&str> = report.coercions.iter().map(|coercion| coercion.raw.as_deref().unwrap()).collect();
+    assert_eq!(raw, ["12.0", "7", "1"]);
+    let (_, report) = with_report(|| from_xml::<Nums>("<nums><a>1</a><b>-2</b><c>true</c><d>0.5</d></nums>"));
+    assert!(report.is_empty(), "{}", report);
+}
+
+#[test]
+fn keeps_the_error_of_a_value_that_can_not_be_coerced() {
+    let (nums, report) = with_report(|| from_xml::<Nums>("<nums><a>abc</a><b>2</b></nums>"));
+    assert_eq!(nums, Nums{ a: 0, b: 2, c: false, d: 0.0 });
+    assert_eq!(report.issues.len(), 1);
+    let message = &report.issues[0].message;
+    assert!(message.contains("invalid type: string \"abc\", expected u32"), "{}", message);
+    assert!(!message.contains("custom: custom"), "{}", message);
+}
+
+#[test]
+fn does_not_coerce_bincode() {
+    let nums = Nums{ a: 5, b: -7, c: true, d: 1.5 };
+    let bytes = bincode::serialize(&nums).unwrap();
+    let (parsed, report) = with_report(|| bincode::deserialize::<Nums>(&bytes).unwrap());
+    assert_eq!(parsed, nums);
+    assert!(report.is_empty());
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+struct Card {
+    number: String,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+struct Invoice {
+    iban: String,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+#[serde(untagged)]
+enum Payment {
+    Card(Card),
+    Invoice(Invoice),
+    Amount(u32),
+}
+
+#[test]
+fn untagged_newtype_variant_matches_on_its_keys() {
+    let (payment, report) = with_report(|| serde_json::from_str::<Payment>(r#"{"iban": "NL01"}"#).unwrap());
+    assert_eq!(payment, Payment::Invoice(Invoice{ iban: "NL01".to_string() }));
+    assert!(report.is_empty());
+    let payment: Payment = serde_json::from_str(r#"{"number": "4111"}"#).unwrap();
+    assert_eq!(payment, Payment::Card(Card{ number: "4111".to_string() }));
+    let payment: Payment = serde_json::from_str("12").unwrap();
+    assert_eq!(payment, Payment::Amount(12));
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+enum External {
+    Unit,
+    Newtype(u32),
+    Struct { a: u32 },
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+#[serde(tag = "type")]
+enum Internal {
+    Card { number: String },
+    Invoice { iban: String },
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+#[serde(tag = "t", content = "c")]
+enum Adjacent {
+    Newtype(u32),
+    Struct { a: u32 },
+}
+
+#[test]
+fn enum_representations() {
+    assert_eq!(serde_json::from_str::<External>(r#""Unit""#).unwrap(), External::Unit);
+    assert_eq!(serde_json::from_str::<External>(r#"{"Newtype": 3}"#).unwrap(), External::Newtype(3));
+    assert_eq!(serde_json::from_str::<External>(r#"{"Struct": {"a": 4}}"#).unwrap(), External::Struct{ a: 4 });
+    assert_eq!(
+        serde_json::from_str::<Internal>(r#"{"type": "Invoice", "iban": "NL01"}"#).unwrap(),
+        Internal::Invoice{ iban: "NL01".to_string() },
+    );
+    assert_eq!(serde_json::from_str::<Adjacent>(r#"{"t": "Newtype", "c": 5}"#).unwrap(), Adjacent::Newtype(5));
+    assert_eq!(serde_json::from_str::<Adjacent>(r#"{"t": "Struct", "c": {"a": 6}}"#).unwrap(), Adjacent::Struct{ a: 6 });
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+struct Slots {
+    slot: [Option<u32>; 2],
+}
+
+#[test]
+fn array_keeps_zero_and_reports_extra_values() {
+    let (slots, report) = with_report(|| from_xml::<Slots>("<slots><slot>0</slot><slot>5</slot><slot>6</slot></slots>"));
+    assert_eq!(slots, Slots{ slot: [Some(0), Some(5)] });
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].path, "slot");
+    assert!(report.issues[0].message.contains("expected an array of length 2"));
+    let strict = deserialize_best_effort::from_reader_strict::<_, Slots>("<slots><slot>0</slot><slot>5</slot><slot>6</slot></slots>".as_bytes());
+    assert!(strict.is_err());
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+enum Status {
+    Active,
+    #[best_effort(other)]
+    Other,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+#[serde(tag = "type")]
+enum Shape {
+    Circle { r: u32 },
+    #[best_effort(other)]
+    Unknown,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Account {
+    name: String,
+    tags: Vec<String>,
+}
+
+#[test]
+fn strict_mode_errors() {
+    let strict = |xml: &str| deserialize_best_effort::from_reader_strict::<_, Account>(xml.as_bytes());
+    assert!(strict("<account><name>a</name><tag>x</tag></account>").unwrap_err().to_string().contains("unknown field `tag`"));
+    assert!(strict("<account><name>a</name><name>b</name></account>").unwrap_err().to_string().contains("duplicate field `name`"));
+    let account = strict("<account><name>a</name><tags>x</tags><tags>y</tags></account>").unwrap();
+    assert_eq!(account, Account{ name: "a".to_string(), tags: vec!["x".to_string(), "y".to_string()] });
+}
+
+fn add_up(total: &mut u32, next_value: u32) {
+    *total += next_value;
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+struct Merged {
+    #[best_effort(merge = "first")]
+    first: String,
+    #[best_effort(merge = "last")]
+    last: Vec<String>,
+    #[best_effort(merge = "concat")]
+    text: String,
+    #[best_effort(merge = "add_up")]
+    total: u32,
+    #[best_effort(merge = "error")]
+    once: String,
+}
+
+#[test]
+fn merge_strategies() {
+    let xml = "<merged><first>a</first><first>b</first><last>a</last><last>b</last><last>c</last>\
+        <text>a</text><text>b</text><total>2</total><total>3</total><once>x</once></merged>";
+    let merged: Merged = from_xml(xml);
+    assert_eq!(merged, Merged{
+        first: "a".to_string(),
+        last: vec!["c".to_string()],
+        text: "ab".to_string(),
+        total: 5,
+        once: "x".to_string(),
+    });
+    let error = serde_xml_rs::from_reader::<_, Merged>("<merged><once>x</once><once>y</once></merged>".as_bytes()).unwrap_err();
+    assert!(error.to_string().contains("duplicate field `once`"), "{}", error);
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+#[serde(default)]
+struct MergedWithDefault {
+    #[best_effort(merge = "last")]
+    tags: Vec<String>,
+}
+
+impl Default for MergedWithDefault {
+    fn default() -> Self {
+        MergedWithDefault{ tags: vec!["default".to_string()] }
+    }
+}
+
+#[test]
+fn merge_last_resets_to_the_container_default() {
+    let parsed: MergedWithDefault = from_xml("<merged><tags>a</tags><tags>b</tags></merged>");
+    assert_eq!(parsed.tags, ["default", "b"]);
+    let parsed: MergedWithDefault = from_xml("<merged></merged>");
+    assert_eq!(parsed.tags, ["default"]);
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+struct Order {
+    #[best_effort(required)]
+    id: u32,
+    note: String,
+}
+
+#[test]
+fn required_field() {
+    let order = serde_json::from_str::<Order>(r#"{"id": 7, "note": "x"}"#).unwrap();
+    assert_eq!(order, Order{ id: 7, note: "x".to_string() });
+    let error = serde_json::from_str::<Order>(r#"{"note": "x"}"#).unwrap_err();
+    assert!(error.to_string().contains("missing field `id`"), "{}", error);
+    let error = serde_json::from_str::<Order>(r#"{"id": "zz", "note": "x"}"#).unwrap_err();
+    assert!(error.to_string().contains("expected u32"), "{}", error);
+    // A struct as a sequence.
+    assert_eq!(serde_json::from_str::<Order>(r#"[7, "x"]"#).unwrap().id, 7);
+    let error = serde_json::from_str::<Order>("[]").unwrap_err();
+    assert!(error.to_string().contains("invalid length 0"), "{}", error);
+    let error = serde_json::from_str::<Order>(r#"["zz", "x"]"#).unwrap_err();
+    assert!(error.to_string().contains("invalid value for required field `id`"), "{}", error);
+}
+
+#[test]
+fn strict_mode_unknown_variant() {
+    assert_eq!(serde_json::from_str::<Status>(r#""Paused""#).unwrap(), Status::Other);
+    let error = serde_json::from_str::<Strict<Status>>(r#""Paused""#).unwrap_err();
+    assert!(error.to_string().contains("unknown variant `Paused`"), "{}", error);
+    assert_eq!(serde_json::from_str::<Strict<Status>>(r#""Active""#).unwrap().0, Status::Active);
+
+    assert_eq!(serde_json::from_str::<Shape>(r#"{"type": "Square"}"#).unwrap(), Shape::Unknown);
+    let error = serde_json::from_str::<Strict<Shape>>(r#"{"type": "Square"}"#).unwrap_err();
+    assert!(error.to_string().contains("unknown variant `Square`"), "{}", error);
+    let error = serde_json::from_str::<Strict<Shape>>(r#"{"r": 1}"#).unwrap_err();
+    assert!(error.to_string().contains("missing field `type`"), "{}", error);
+}
+
+#[derive(DeserializeBestEffort, SerializeBestEffort, Debug, PartialEq, Default)]
+struct Root {
+    field1: Vec<String>,
+    field2: Vec<String>,
+    #[best_effort(ordered)]
+    order: DocumentOrder,
+    #[serde(flatten)]
+    unknown: HashMap<String, Value>,
+}
+
+#[test]
+fn test_xml_round_trip() {
+    let parsed: Root = from_xml(include_str!("../../test.xml"));
+    assert_eq!(parsed.field1, ["abc", "def", "ghi"]);
+    assert_eq!(parsed.unknown.len(), 1);
+    let xml = serde_xml_rs::to_string(&parsed).unwrap();
+    assert!(xml.starts_with("<Root><field1>abc</field1><field1>def</field1><field2>lmn</field2>"), "{}", xml);
+    let parsed_again: Root = from_xml(&xml);
+    assert_eq!(parsed_again, parsed);
+}
+
+#[test]
+fn document_order_indexes() {
+    let mut order = DocumentOrder::default();
+    for i in 0..10_000 {
+        order.push(["a", "b", "c"][i % 3]);
+    }
+    assert_eq!(order.len(), 10_000);
+    assert_eq!(order.count("a"), 3334);
+    assert_eq!(order.count("c"), 3333);
+    assert_eq!(order.count("d"), 0);
+    let last = order.iter().last().unwrap();
+    assert_eq!((last.key.as_str(), last.index), ("a", 3333));
+    assert_eq!(order.clone(), order);
+}
+
+#[derive(DeserializeBestEffort, SerializeBestEffort, Debug, PartialEq, Default)]
+struct Inner {
+    field3: Vec<u32>,
+}
+
+#[derive(DeserializeBestEffort, SerializeBestEffort, Debug, PartialEq, Default)]
+struct Interleaved {
+    field1: Vec<u32>,
+    #[serde(flatten)]
+    inner: Inner,
+    #[best_effort(ordered)]
+    order: DocumentOrder,
+    #[best_effort(catch_all)]
+    unknown: deserialize_best_effort::UnknownFields,
+}
+
+#[test]
+fn writes_many_values_in_the_order_of_the_input() {
+    let keys = ["field1", "field3", "other"];
+    let xml: String = (0..3000).map(|i| format!("<{0}>{1}</{0}>", keys[i % 3], i)).collect();
+    let parsed: Interleaved = from_xml(&format!("<interleaved>{}</interleaved>", xml));
+    assert_eq!(parsed.field1.len(), 1000);
+    assert_eq!(parsed.unknown.len(), 1000);
+    let written = serde_xml_rs::to_string(&parsed).unwrap();
+    assert_eq!(written, format!("<Interleaved>{}</Interleaved>", xml));
+}
+
+#[test]
+fn writes_many_distinct_unknown_keys() {
+    let xml: String = (0..5000).map(|i| format!("<key{0}>{0}</key{0}>", i)).collect();
+    let parsed: Interleaved = from_xml(&format!("<interleaved>{}</interleaved>", xml));
+    assert_eq!(parsed.unknown.len(), 5000);
+    assert_eq!(serde_xml_rs::to_string(&parsed).unwrap(), format!("<Interleaved>{}</Interleaved>", xml));
+    let json = serde_json::to_value(&parsed).unwrap();
+    assert_eq!(json.as_object().unwrap().len(), 5000);
+    assert_eq!(json["key4999"], "4999");
+}
+
+#[test]
+fn catch_all_struct_is_a_json_object() {
+    let parsed: Root = serde_json::from_str(r#"{"field1": "a", "extra": 1, "field2": "b"}"#).unwrap();
+    let json = serde_json::to_string(&parsed).unwrap();
+    assert_eq!(json, r#"{"field1":"a","extra":1,"field2":"b"}"#);
+}
+
+#[derive(DeserializeBestEffort, SerializeBestEffort, Debug, PartialEq, Default)]
+struct Scores {
+    scores: BTreeMap<String, u32>,
+    history: HashMap<String, Vec<u32>>,
+    #[serde(flatten)]
+    rest: HashMap<String, Value>,
+}
+
+#[test]
+fn map_fields() {
+    let xml = "<scores><scores><a>1</a><b>x</b></scores><history><a>1</a></history><other>2</other>\
+        <scores><c>3</c></scores><history><a>2</a><b>3</b></history></scores>";
+    let (scores, report) = with_report(|| from_xml::<Scores>(xml));
+    let expected: BTreeMap<String, u32> = vec![("a".to_string(), 1), ("c".to_string(), 3)].into_iter().collect();
+    assert_eq!(scores.scores, expected);
+    assert_eq!(scores.history["a"], [1, 2]);
+    assert_eq!(scores.history["b"], [3]);
+    assert_eq!(scores.rest.keys().collect::<Vec<_>>(), ["other"]);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].path, "scores.b");
+
+    let parsed: Scores = serde_json::from_str(r#"{"scores": {"a": 1, "b": 2}}"#).unwrap();
+    assert_eq!(serde_json::to_string(&parsed).unwrap(), r#"{"scores":{"a":1,"b":2}}"#);
+    let parsed: Scores = from_xml("<scores><history><a>1</a><a>2</a></history></scores>");
+    let xml = serde_xml_rs::to_string(&parsed).unwrap();
+    assert_eq!(xml, "<Scores><history><a>1</a><a>2</a></history></Scores>");
+    assert_eq!(from_xml::<Scores>(&xml), parsed);
+}
+
+/// Not `serde_json::Map`, so a flattened struct and not the catch-all field.
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Map {
+    color: String,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Styled {
+    #[serde(flatten)]
+    map: Map,
+    #[serde(flatten)]
+    rest: HashMap<String, Value>,
+}
+
+#[test]
+fn struct_named_map_is_not_a_map() {
+    let styled: Styled = serde_json::from_str(r#"{"color": "red", "size": 2}"#).unwrap();
+    assert_eq!(styled.map.color, "red");
+    assert_eq!(styled.rest.get("size"), Some(&Value::from(2)));
+}
+
+#[derive(DeserializeBestEffort, SerializeBestEffort, Debug, PartialEq, Default)]
+struct Item {
+    #[serde(rename = "id")]
+    #[best_effort(attribute)]
+    id_attr: String,
+    #[best_effort(element)]
+    id: Vec<String>,
+    name: String,
+    #[serde(flatten)]
+    rest: HashMap<String, Value>,
+}
+
+const ITEM: &str = r#"<item id="1" name="a" x="2"><id>3</id><extra>4</extra></item>"#;
+
+#[test]
+fn attributes_with_the_wrapper() {
+    let item: Item = deserialize_best_effort::from_reader_with_attributes(ITEM.as_bytes()).unwrap();
+    assert_eq!(item.id_attr, "1");
+    assert_eq!(item.id, ["3"]);
+    assert_eq!(item.name, "a");
+    let mut keys: Vec<&str> = item.rest.keys().map(String::as_str).collect();
+    keys.sort();
+    assert_eq!(keys, ["@x", "extra"]);
+
+    let error = serde_xml_rs::to_string(&item).unwrap_err();
+    assert!(error.to_string().contains("can not write the attribute `@id`"), "{}", error);
+    let json = serde_json::to_value(&item).unwrap();
+    assert_eq!(json, serde_json::json!({"@id": "1", "id": "3", "name": "a", "@x": "2", "extra": "4"}));
+}
+
+#[test]
+fn attributes_without_the_wrapper_are_elements() {
+    let item: Item = from_xml(ITEM);
+    assert_eq!(item.id_attr, "");
+    assert_eq!(item.id, ["1", "3"]);
+    assert!(item.rest.contains_key("x"));
+}
+
+#[test]
+fn attribute_keys_in_json() {
+    let item: Item = serde_json::from_str(r#"{"@id": "1", "id": "2", "@name": "n", "@y": 3}"#).unwrap();
+    assert_eq!(item.id_attr, "1");
+    assert_eq!(item.id, ["2"]);
+    assert_eq!(item.name, "n");
+    assert_eq!(item.rest.get("@y"), Some(&Value::from(3)));
+    assert_eq!(serde_json::to_string(&item).unwrap(), r#"{"@id":"1","id":"2","name":"n","@y":3}"#);
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Unmarked {
+    name: String,
+    #[best_effort(catch_all)]
+    unknown: deserialize_best_effort::UnknownFields,
+}
+
+#[test]
+fn attribute_keys_without_marked_fields_are_unknown() {
+    let unmarked: Unmarked = serde_json::from_str(r#"{"@name": "a", "name": "b"}"#).unwrap();
+    assert_eq!(unmarked.name, "b");
+    assert_eq!(unmarked.unknown.len(), 1);
+    assert_eq!(unmarked.unknown.iter().next().unwrap().key, "@name");
+}
+
+/// The keys of a map of `serde-xml-rs` with its `size_hint` before each key.
+struct SizeHints(Vec<(Option<usize>, String)>);
+
+impl<'de> de::Deserialize<'de> for SizeHints {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SizeHintsVisitor;
+
+        impl<'de> de::Visitor<'de> for SizeHintsVisitor {
+            type Value = SizeHints;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a map")
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<SizeHints, A::Error> {
+                let mut hints = Vec::new();
+                loop {
+                    let size_hint = map.size_hint();
+                    match map.next_key::<String>()? {
+                        Some(key) => hints.push((size_hint, key)),
+                        None => return Ok(SizeHints(hints)),
+                    }
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+
+        deserializer.deserialize_map(SizeHintsVisitor)
+    }
+}
+
+// `WithAttributes` relies on this: while `serde-xml-rs` gives the attributes,
+// `size_hint` is the number of attributes that are left.
+#[test]
+fn xml_size_hint_counts_the_attributes_left() {
+    let hints: SizeHints = from_xml(r#"<r a="1" b="2"><c>3</c></r>"#);
+    assert_eq!(hints.0, [(Some(2), "a".to_string()), (Some(1), "b".to_string()), (Some(0), "c".to_string())]);
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Tagged {
+    #[best_effort(attribute, collision = "error")]
+    id: String,
+}
+
+#[test]
+fn collision_error() {
+    let tagged: Tagged = deserialize_best_effort::from_reader_with_attributes(r#"<t id="1"/>"#.as_bytes()).unwrap();
+    assert_eq!(tagged.id, "1");
+    let error = deserialize_best_effort::from_reader_with_attributes::<_, Tagged>("<t><id>2</id></t>".as_bytes()).unwrap_err();
+    assert!(error.to_string().contains("found element `id`, expected attribute `id`"), "{}", error);
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+struct CamelCase {
+    first_name: String,
+    #[serde(rename = "ID")]
+    user_id: u32,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+struct ScreamingKebab {
+    first_name: String,
+    #[serde(alias = "last")]
+    last_name: String,
+}
+
+#[test]
+fn rename_and_rename_all() {
+    let parsed: CamelCase = from_xml("<r><firstName>a</firstName><ID>7</ID><first_name>b</first_name></r>");
+    assert_eq!(parsed, CamelCase{ first_name: "a".to_string(), user_id: 7 });
+    let parsed: CamelCase = serde_json::from_str(r#"{"firstName": "a", "userId": 8}"#).unwrap();
+    assert_eq!(parsed, CamelCase{ first_name: "a".to_string(), user_id: 0 });
+
+    let parsed: ScreamingKebab = from_xml("<r><FIRST-NAME>a</FIRST-NAME><last>b</last></r>");
+    assert_eq!(parsed, ScreamingKebab{ first_name: "a".to_string(), last_name: "b".to_string() });
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct NoCatchAll {
+    name: String,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct NamedCatchAll {
+    name: String,
+    #[best_effort(catch_all)]
+    extra: BTreeMap<String, Value>,
+}
+
+#[test]
+fn catch_all_field() {
+    let (parsed, report) = with_report(|| from_xml::<NoCatchAll>("<r><name>a</name><other>b</other></r>"));
+    assert_eq!(parsed, NoCatchAll{ name: "a".to_string() });
+    assert!(report.is_empty());
+
+    let parsed: NamedCatchAll = serde_json::from_str(r#"{"name": "a", "other": 1, "more": [2]}"#).unwrap();
+    assert_eq!(parsed.name, "a");
+    assert_eq!(parsed.extra.keys().collect::<Vec<_>>(), ["more", "other"]);
+    assert_eq!(parsed.extra["other"], Value::from(1));
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Sku(String);
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Coord(f64, f64);
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Marker;
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Product {
+    sku: Sku,
+    at: Coord,
+    marker: Marker,
+}
+
+#[test]
+fn tuple_newtype_and_unit_structs() {
+    let (coord, report) = with_report(|| serde_json::from_str::<Coord>(r#"["x", 2.5, 3]"#).unwrap());
+    assert_eq!(coord, Coord(0.0, 2.5));
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].path, "0");
+    assert_eq!(serde_json::from_str::<Coord>("[1.5]").unwrap(), Coord(1.5, 0.0));
+
+    let product: Product = serde_json::from_str(r#"{"sku": "A1", "at": [1, 2], "marker": {"a": 1}}"#).unwrap();
+    assert_eq!(product, Product{ sku: Sku("A1".to_string()), at: Coord(1.0, 2.0), marker: Marker });
+    let product: Product = from_xml("<product><sku>B2</sku><marker/></product>");
+    assert_eq!(product.sku, Sku("B2".to_string()));
+    assert_eq!(bincode::deserialize::<Sku>(&bincode::serialize("C3").unwrap()).unwrap(), Sku("C3".to_string()));
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Envelope<T> where T: Clone {
+    id: u32,
+    body: T,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Borrowed<'a> {
+    name: &'a str,
+    sizes: Vec<u8>,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+#[serde(bound = "T: de::Deserialize<'de> + deserialize_best_effort::DeserializeBestEffortTypes<'de, T> + Default")]
+struct Bounded<T> {
+    value: T,
+}
+
+#[test]
+fn generics_and_borrowed_lifetimes() {
+    let envelope: Envelope<u64> = from_xml("<e><id>1</id><body>x</body></e>");
+    assert_eq!(envelope, Envelope{ id: 1, body: 0 });
+    let envelope: Envelope<String> = serde_json::from_str(r#"{"id": 2, "body": "text"}"#).unwrap();
+    assert_eq!(envelope, Envelope{ id: 2, body: "text".to_string() });
+
+    let json = r#"{"name": "borrowed", "sizes": 3}"#.to_string();
+    let borrowed: Borrowed = serde_json::from_str(&json).unwrap();
+    assert_eq!(borrowed, Borrowed{ name: "borrowed", sizes: vec![3] });
+
+    let bounded: Bounded<bool> = serde_json::from_str(r#"{"value": "yes"}"#).unwrap();
+    assert!(bounded.value);
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Primitives {
+    a: i8,
+    b: i16,
+    c: i128,
+    d: isize,
+    e: u8,
+    f: u16,
+    g: u64,
+    h: u128,
+    i: usize,
+    j: f32,
+    k: char,
+    l: Box<str>,
+    m: std::borrow::Cow<'static, str>,
+}
+
+#[test]
+fn primitive_types() {
+    let xml = "<p><a>-8</a><b>16</b><c>-128</c><d>7</d><e>300</e><f>0x10</f><g>64</g><h>128</h>\
+        <i>1,000</i><j>1.5</j><k>z</k><l>boxed</l><m>cow</m></p>";
+    let (parsed, report) = with_report(|| from_xml::<Primitives>(xml));
+    assert_eq!(parsed, Primitives{
+        a: -8, b: 16, c: -128, d: 7, e: 0, f: 16, g: 64, h: 128, i: 1000, j: 1.5, k: 'z',
+        l: "boxed".into(), m: "cow".into(),
+    });
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].path, "e");
+
+    let parsed: Primitives = serde_json::from_str(r#"{"k": "too long", "l": 5, "m": "borrowed"}"#).unwrap();
+    assert_eq!(parsed.k, '\0');
+    assert_eq!(&*parsed.l, "");
+    assert_eq!(parsed.m, "borrowed");
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Node {
+    name: String,
+    parent: Option<Box<Node>>,
+    // `Vec<Box<T>>` has its own impl, this tests it.
+    #[allow(clippy::vec_box)]
+    node: Vec<Box<Node>>,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Wrappers {
+    rc: Rc<Vec<String>>,
+    arc: Arc<u32>,
+    cell: Cell<u32>,
+    ref_cell: RefCell<Vec<u32>>,
+    tree_set: BTreeSet<u32>,
+    hash_set: HashSet<String>,
+    queue: VecDeque<u32>,
+}
+
+#[test]
+fn wrappers_and_collections() {
+    let xml = "<node><name>root</name><parent><name>up</name></parent>\
+        <node><name>a</name><node><name>a1</name></node></node><node><name>b</name></node></node>";
+    let root: Node = from_xml(xml);
+    assert_eq!(root.parent.as_ref().unwrap().name, "up");
+    let names: Vec<&str> = root.node.iter().map(|node| node.name.as_str()).collect();
+    assert_eq!(names, ["a", "b"]);
+    assert_eq!(root.node[0].node[0].name, "a1");
+
+    let xml = "<w><rc>a</rc><rc>b</rc><arc>1</arc><arc>2</arc><cell>x</cell><ref_cell>3</ref_cell><ref_cell>4</ref_cell>\
+        <tree_set>2</tree_set><tree_set>1</tree_set><tree_set>2</tree_set><hash_set>h</hash_set><hash_set>h</hash_set>\
+        <queue>5</queue><queue>6</queue></w>";
+    let (parsed, report) = with_report(|| from_xml::<Wrappers>(xml));
+    assert_eq!(*parsed.rc, ["a", "b"]);
+    assert_eq!(*parsed.arc, 2);
+    assert_eq!(parsed.cell.get(), 0);
+    assert_eq!(*parsed.ref_cell.borrow(), [3, 4]);
+    assert_eq!(parsed.tree_set.into_iter().collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(parsed.hash_set.len(), 1);
+    assert_eq!(parsed.queue, [5, 6]);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].path, "cell");
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+struct Seconds(u32);
+
+fn thirty_seconds() -> Seconds {
+    Seconds(30)
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+struct Timeouts {
+    #[serde(default = "thirty_seconds")]
+    connect: Seconds,
+    retries: u32,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq)]
+#[serde(default)]
+struct Limits {
+    max: u32,
+    name: String,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits{ max: 10, name: "limit".to_string() }
+    }
+}
+
+#[test]
+fn default_functions_and_container_default() {
+    let timeouts: Timeouts = from_xml("<t><retries>3</retries></t>");
+    assert_eq!(timeouts, Timeouts{ connect: Seconds(30), retries: 3 });
+    let timeouts: Timeouts = from_xml("<t><connect>5</connect></t>");
+    assert_eq!(timeouts, Timeouts{ connect: Seconds(5), retries: 0 });
+
+    let limits: Limits = from_xml("<l><name>n</name></l>");
+    assert_eq!(limits, Limits{ max: 10, name: "n".to_string() });
+    let limits: Limits = serde_json::from_str(r#"{"max": "x"}"#).unwrap();
+    assert_eq!(limits, Limits{ max: 10, name: "limit".to_string() });
+}
+
+#[derive(DeserializeBestEffort, SerializeBestEffort, Debug, PartialEq, Default)]
+struct Cached {
+    name: String,
+    #[serde(skip)]
+    cache: u32,
+    #[serde(skip_deserializing)]
+    computed: String,
+    #[serde(skip_serializing)]
+    secret: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    note: String,
+    #[best_effort(catch_all)]
+    unknown: deserialize_best_effort::UnknownFields,
+}
+
+#[test]
+fn skipped_fields() {
+    let xml = "<c><name>a</name><cache>1</cache><computed>x</computed><secret>s</secret><note></note></c>";
+    let mut cached: Cached = from_xml(xml);
+    assert_eq!(cached.cache, 0);
+    assert_eq!(cached.computed, "");
+    assert_eq!(cached.secret, "s");
+    assert!(cached.unknown.is_empty());
+
+    cached.cache = 5;
+    cached.computed = "y".to_string();
+    assert_eq!(serde_xml_rs::to_string(&cached).unwrap(), "<Cached><name>a</name><computed>y</computed></Cached>");
+    cached.note = "n".to_string();
+    assert_eq!(serde_json::to_string(&cached).unwrap(), r#"{"name":"a","computed":"y","note":"n"}"#);
+}
+
+/// `20201018` as `2020-10-18`.
+fn parse_date<'de, D: de::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let text: String = de::Deserialize::deserialize(deserializer)?;
+    if text.len() != 8 || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(de::Error::custom(format!("invalid date `{}`", text)));
+    }
+    Ok(format!("{}-{}-{}", &text[..4], &text[4..6], &text[6..]))
+}
+
+mod hex {
+    use serde::de;
+
+    pub fn deserialize<'de, D: de::Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        let text: String = de::Deserialize::deserialize(deserializer)?;
+        u32::from_str_radix(text.trim_start_matches('#'), 16).map_err(de::Error::custom)
+    }
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Custom {
+    #[serde(deserialize_with = "parse_date")]
+    dates: Vec<String>,
+    #[serde(with = "hex")]
+    #[best_effort(merge = "first")]
+    color: u32,
+}
+
+#[test]
+fn deserialize_with_and_with() {
+    let xml = "<c><dates>20201018</dates><dates>bad</dates><dates>20210101</dates><color>#ff</color><color>#10</color></c>";
+    let (custom, report) = with_report(|| from_xml::<Custom>(xml));
+    assert_eq!(custom.dates, ["2020-10-18", "", "2021-01-01"]);
+    assert_eq!(custom.color, 255);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].path, "dates");
+    assert!(report.issues[0].message.contains("invalid date `bad`"), "{}", report.issues[0].message);
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Meta {
+    version: u32,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Audit {
+    created_by: String,
+    #[best_effort(merge = "first")]
+    source: String,
+    name: String,
+    #[serde(flatten)]
+    meta: Meta,
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Audited {
+    name: String,
+    #[serde(flatten)]
+    audit: Audit,
+    #[serde(flatten)]
+    rest: HashMap<String, Value>,
+}
+
+#[test]
+fn typed_flatten() {
+    let xml = "<a><name>n</name><created_by>me</created_by><source>x</source><source>y</source>\
+        <version>oops</version><version>2</version><other>o</other></a>";
+    let (audited, report) = with_report(|| from_xml::<Audited>(xml));
+    assert_eq!(audited.name, "n");
+    assert_eq!(audited.audit, Audit{
+        created_by: "me".to_string(),
+        source: "x".to_string(),
+        name: String::new(),
+        meta: Meta{ version: 2 },
+    });
+    assert_eq!(audited.rest.keys().collect::<Vec<_>>(), ["other"]);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].path, "version");
+}
+
+#[test]
+fn error_messages_use_the_wire_names() {
+    let strict = deserialize_best_effort::from_reader_strict::<_, CamelCase>("<r><userId>1</userId></r>".as_bytes());
+    let error = strict.unwrap_err().to_string();
+    assert!(error.contains("unknown field `userId`, expected `firstName` or `ID`"), "{}", error);
+
+    let strict = deserialize_best_effort::from_reader_strict::<_, ScreamingKebab>("<r><first_name>a</first_name></r>".as_bytes());
+    let error = strict.unwrap_err().to_string();
+    assert!(error.contains("expected one of `FIRST-NAME`, `LAST-NAME`, `last`"), "{}", error);
+
+    // bincode reads a struct as a sequence with the length of `FIELDS`.
+    let bytes = bincode::serialize(&("a", 3u32)).unwrap();
+    let parsed: CamelCase = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(parsed, CamelCase{ first_name: "a".to_string(), user_id: 3 });
+}
+
+#[derive(DeserializeBestEffort, Debug, PartialEq, Default)]
+struct Row {
+    id: u32,
+    name: String,
+    score: f64,
+    #[best_effort(catch_all)]
+    extra: BTreeMap<String, Value>,
+}
+
+#[test]
+fn struct_from_a_sequence() {
+    let (row, report) = with_report(|| serde_json::from_str::<Row>(r#"[1, 2, 3.5, "x", {"y": 1}]"#).unwrap());
+    assert_eq!(row.id, 1);
+    assert_eq!(row.name, "");
+    assert_eq!(row.score, 3.5);
+    assert_eq!(row.extra.keys().collect::<Vec<_>>(), ["3", "4"]);
+    assert_eq!(row.extra["3"], "x");
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].path, "1");
+
+    let row: Row = serde_json::from_str("[7]").unwrap();
+    assert_eq!(row, Row{ id: 7, ..Row::default() });
+    let row: Row = serde_json::from_str("[]").unwrap();
+    assert_eq!(row, Row::default());
+}
+
+#[test]
+fn unknown_fields_keep_every_key_in_order() {
+    let xml = "<u><a>1</a><name>n</name><b>2</b><a>3</a></u>";
+    let unmarked: Unmarked = from_xml(xml);
+    let unknown = &unmarked.unknown;
+    let keys: Vec<(&str, usize)> = unknown.iter().map(|field| (field.key.as_str(), field.position)).collect();
+    assert_eq!(keys, [("a", 0), ("b", 2), ("a", 3)]);
+    assert_eq!(unknown.get_all("a").collect::<Vec<_>>(), [&serde_json::json!({"$value": "1"}), &serde_json::json!({"$value": "3"})]);
+
+    let unmarked: Unmarked = serde_json::from_str(r#"{"a": 1, "name": "n", "b": 2, "a": 3}"#).unwrap();
+    assert_eq!(Value::Object(unmarked.unknown.to_map()), serde_json::json!({"a": [1, 3], "b": 2}));
+}
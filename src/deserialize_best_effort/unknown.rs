@@ -0,0 +1,90 @@
+// A catch-all field that keeps every unknown key.
+//
+// A `HashMap<String, Value>` keeps only the last value of a repeated key and
+// loses the order. `UnknownFields` keeps every key in the order of the input,
+// with the value as it was read and the position of the key in the map.
+use std::slice;
+
+use serde_json::{Map, Value};
+
+use super::DeserializeBestEffortTypes;
+
+/// One key that is not a field of the struct.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownField {
+    pub key: String,
+    /// The value as it was read, ex: `{"$value": "text"}` for an xml element.
+    pub value: Value,
+    /// The number of keys before it in the map of the struct, this is also
+    /// the position in the `DocumentOrder` of the struct.
+    pub position: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UnknownFields {
+    fields: Vec<UnknownField>,
+}
+
+impl UnknownFields {
+    pub fn iter(&self) -> slice::Iter<'_, UnknownField> {
+        self.fields.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// All values of `key`, in the order of the input.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+        self.fields.iter()
+            .filter(move |field| field.key == key)
+            .map(|field| &field.value)
+    }
+
+    /// One value per key. A key that was found more than once has an array
+    /// with all its values.
+    pub fn to_map(&self) -> Map<String, Value> {
+        let mut map = Map::new();
+        for field in &self.fields {
+            if map.contains_key(&field.key) {
+                continue;
+            }
+            let mut values: Vec<Value> = self.get_all(&field.key).cloned().collect();
+            let value = match values.len() {
+                1 => values.remove(0),
+                _ => Value::Array(values),
+            };
+            map.insert(field.key.clone(), value);
+        }
+        map
+    }
+}
+
+impl<'a> IntoIterator for &'a UnknownFields {
+    type Item = &'a UnknownField;
+    type IntoIter = slice::Iter<'a, UnknownField>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.fields.iter()
+    }
+}
+
+impl<'de> DeserializeBestEffortTypes<'de, Value> for UnknownFields {
+    /// Without a position the value is placed after the last one.
+    fn add_data(&mut self, key: &str, next_value: Value){
+        let position = self.fields.last().map_or(0, |field| field.position + 1);
+        self.add_data_at(key, next_value, position);
+    }
+
+    fn add_data_at(&mut self, key: &str, next_value: Value, position: usize){
+        self.fields.push(UnknownField{ key: key.to_string(), value: next_value, position });
+    }
+
+    fn keeps_values(&self) -> bool {
+        true
+    }
+}
@@ -4,7 +4,10 @@ use std::fmt;
 
 use serde::de::{self, Deserialize, Deserializer, Visitor, SeqAccess, MapAccess};
 
-use crate::deserialize_best_effort::{DeserializeBestEffort, DeserializeBestEffortTypes};
+use crate::deserialize_best_effort::{
+    DeserializeBestEffort, DeserializeBestEffortTypes, next_value_or_default, next_element_or_default, next_element_or_report,
+    strict_result,
+};
 use crate::RootWorkingManualImpl;
 use std::collections::HashMap;
 use serde_json::Value;
@@ -19,7 +22,7 @@ impl<'de> Deserialize<'de> for RootWorkingManualImpl {
     where
         D: Deserializer<'de>,
     {
-        enum Field { Field1, Field2, Unknown(String) };
+        enum Field { Field1, Field2, Unknown(String) }
         // This part could also be generated independently by:
         //
         //    #[derive(Deserialize)]
@@ -65,19 +68,25 @@ impl<'de> Deserialize<'de> for RootWorkingManualImpl {
                 formatter.write_str("struct RootWorkingManualImpl")
             }
 
+            // Used by formats that give a struct as a sequence (bincode, a JSON array)
+            // Missing elements and elements that can not be parsed use `Default`,
+            // extra elements go to `unknown` with their index as key.
             fn visit_seq<V>(self, mut seq: V) -> Result<RootWorkingManualImpl, V::Error>
             where
                 V: SeqAccess<'de>,
             {
-                // note used for struct it seems
-                let field1 = seq.next_element()?
-                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
-                let field2 = seq.next_element()?
-                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let field1: Vec<String> = next_element_or_default(&mut seq, 0);
+                let field2: Vec<String> = next_element_or_default(&mut seq, 1);
+                let mut unknown: HashMap<String, Value> = Default::default();
+                let mut index = 2;
+                while let Some(Some(next_value)) = next_element_or_report(&mut seq, index) {
+                    unknown.add_data(&index.to_string(), next_value);
+                    index += 1;
+                }
                 Ok(RootWorkingManualImpl{
                     field1,
                     field2,
-                    ..Default::default()
+                    unknown,
                 })
             }
 
@@ -91,15 +100,15 @@ impl<'de> Deserialize<'de> for RootWorkingManualImpl {
                 while let Some(key) = map.next_key()? {
                     match key {
                         Field::Field1 => {
-                            let next_value = map.next_value().unwrap_or_default();
+                            let next_value = next_value_or_default(&mut map, "field1");
                             field1.add_data("field1",next_value);
                         }
                         Field::Field2 => {
-                            let next_value = map.next_value().unwrap_or_default();
+                            let next_value = next_value_or_default(&mut map, "field2");
                             field2.add_data("field2",next_value);
                         }
                         Field::Unknown(key_name) => {
-                            let next_value = map.next_value().unwrap_or_default();
+                            let next_value = next_value_or_default(&mut map, &key_name);
                             unknown.add_data(&key_name,next_value);
                         }
                     }
@@ -112,7 +121,7 @@ impl<'de> Deserialize<'de> for RootWorkingManualImpl {
                 })
             }
         }
-        const FIELDS: &'static [&'static str] = &["field1", "field2"];
-        deserializer.deserialize_struct("RootWorkingManualImpl" , FIELDS, RootWorkingManualImplVisitor)
+        const FIELDS: &[&str] = &["field1", "field2"];
+        strict_result(deserializer.deserialize_struct("RootWorkingManualImpl" , FIELDS, RootWorkingManualImplVisitor))
    }
 }
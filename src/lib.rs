@@ -0,0 +1,5 @@
+//! The runtime of `#[derive(DeserializeBestEffort)]` and
+//! `#[derive(SerializeBestEffort)]` from the `custom_derive` crate.
+//! The generated code uses it as `deserialize_best_effort`, so that name has
+//! to be in scope where the derive is used.
+pub mod deserialize_best_effort;
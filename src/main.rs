@@ -6,9 +6,9 @@ use std::io::BufReader;
 
 
 // Imports needed for custom trait/derive
-mod deserialize_best_effort;
-use deserialize_best_effort::{DeserializeBestEffort, DeserializeBestEffortTypes};
-use custom_derive::{DeserializeBestEffort};
+use serde_deserializer_best_effort::deserialize_best_effort;
+use deserialize_best_effort::BestEffortReport;
+use custom_derive::{DeserializeBestEffort, SerializeBestEffort};
 use serde::de;
 use std::collections::HashMap;
 use serde_json::Value;
@@ -23,7 +23,7 @@ fn main() {
 
     #[allow(dead_code)]
     enum TestSelect{
-        NotWorking, Working, WorkingManualImpl,
+        NotWorking, Working, WorkingManualImpl, WorkingWithReport,
     }
     let selected_test: TestSelect = TestSelect::WorkingManualImpl;
 
@@ -41,6 +41,11 @@ fn main() {
         TestSelect::WorkingManualImpl => {
             let parse_xml = parse_file_working_manual_impl(file_path);
             println!("Print Parsed output: {:#?}", parse_xml);
+        },
+        TestSelect::WorkingWithReport => {
+            let (parse_xml, report) = parse_file_working_with_report(file_path);
+            println!("Print Parsed output: {:#?}", parse_xml);
+            println!("Print Report:\n{}", report);
         }
     }
 }
@@ -55,13 +60,21 @@ pub struct RootNotWorking {
 // Default trait is needed for providing data, but could be removed if
 // `Option<T>` is used for variables as in the example:
 // https://serde.rs/deserialize-struct.html
-#[derive(Serialize, Debug, DeserializeBestEffort, Clone, Default)]
+// `SerializeBestEffort` writes it back like it was read: the lists as repeated
+// tags and the unknown tags with their own name.
+#[derive(SerializeBestEffort, Debug, DeserializeBestEffort, Clone, Default)]
 pub struct RootWorking {
+    // Empty lists are not written, so serializing and parsing again does not
+    // add an empty tag.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub field1: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub field2: Vec<String>,
 
-    // this is needed because of how I wrote the deserializer
-    // but is not needed for actual demo
+    // All tags that are not in the struct end up here.
+    // The catch-all field is the `#[serde(flatten)]` map
+    // (or a field marked with `#[best_effort(catch_all)]`),
+    // without one the unknown tags are dropped.
     #[serde(flatten)]
     pub unknown: HashMap<String, Value>,
 }
@@ -100,3 +113,9 @@ pub fn parse_file_working(filename: &str) -> RootWorking{
 pub fn parse_file_working_manual_impl(filename: &str) -> RootWorking{
     read_xml_file( filename ).unwrap()
 }
+
+pub fn parse_file_working_with_report(filename: &str) -> (RootWorking, BestEffortReport){
+    let file = File::open(filename).unwrap();
+    let reader = BufReader::new(file);
+    deserialize_best_effort::from_reader_with_report(reader).unwrap()
+}
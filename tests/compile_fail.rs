@@ -0,0 +1,6 @@
+// The derives point their errors at the field or attribute that is wrong.
+#[test]
+fn compile_fail() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,9 @@
+use custom_derive::DeserializeBestEffort;
+
+#[derive(DeserializeBestEffort)]
+struct Config {
+    #[serde(alias = 42)]
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use custom_derive::DeserializeBestEffort;
+
+#[derive(DeserializeBestEffort)]
+struct Log {
+    #[best_effort(merge = "concat")]
+    lines: Vec<String>,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use custom_derive::DeserializeBestEffort;
+
+#[derive(DeserializeBestEffort)]
+struct Config {
+    name: String,
+    #[best_effort(catch_all)]
+    unknown: std::collections::HashMap<String, String>,
+    #[best_effort(catch_all)]
+    other: std::collections::HashMap<String, String>,
+}
+
+fn main() {}
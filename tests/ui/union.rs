@@ -0,0 +1,9 @@
+use custom_derive::DeserializeBestEffort;
+
+#[derive(DeserializeBestEffort)]
+union Number {
+    int: i64,
+    float: f64,
+}
+
+fn main() {}
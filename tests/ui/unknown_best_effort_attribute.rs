@@ -0,0 +1,9 @@
+use custom_derive::DeserializeBestEffort;
+
+#[derive(DeserializeBestEffort)]
+struct Config {
+    #[best_effort(requierd)]
+    name: String,
+}
+
+fn main() {}
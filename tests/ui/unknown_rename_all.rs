@@ -0,0 +1,9 @@
+use custom_derive::DeserializeBestEffort;
+
+#[derive(DeserializeBestEffort)]
+#[serde(rename_all = "kebab")]
+struct Config {
+    user_name: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use custom_derive::DeserializeBestEffort;
+
+#[derive(DeserializeBestEffort)]
+struct Config {
+    #[serde(default = "default name")]
+    name: String,
+}
+
+fn main() {}
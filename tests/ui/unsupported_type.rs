@@ -0,0 +1,11 @@
+use custom_derive::DeserializeBestEffort;
+use serde_deserializer_best_effort::deserialize_best_effort;
+use serde::de;
+
+#[derive(DeserializeBestEffort)]
+struct Timeout {
+    name: String,
+    after: std::time::Duration,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use custom_derive::SerializeBestEffort;
+use serde_deserializer_best_effort::deserialize_best_effort;
+
+#[derive(SerializeBestEffort)]
+struct Timeout {
+    name: String,
+    after: std::time::Duration,
+}
+
+fn main() {}